@@ -0,0 +1,92 @@
+use std::{collections::BTreeMap, path::Path};
+
+use tokio::fs;
+
+use crate::{
+    download::{download_all, DownloadTask},
+    http::get_bytes,
+    mojang::get_client_manifest,
+    types::AssetIndex,
+    utils::sha1_hex,
+};
+
+const RESOURCES_URL: &str = "https://resources.download.minecraft.net";
+
+/// Раскладывает ассеты так же, как лаунчер Mojang:
+/// assets/indexes/<id>.json, assets/objects/<xx>/<hash>,
+/// а для старых индексов ещё assets/virtual/<id>/<path> или resources/<path>
+pub async fn download_assets(
+    server_name: &str,
+    mc_version: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let asset_index = get_client_manifest(mc_version).await?.assetIndex;
+
+    let index_bytes = get_bytes(&asset_index.url).await?;
+
+    if index_bytes.len() as u64 != asset_index.size || sha1_hex(&index_bytes) != asset_index.sha1 {
+        return Err(format!("Asset index {} is corrupted", asset_index.id).into());
+    }
+
+    let index: AssetIndex = serde_json::from_slice(&index_bytes)?;
+
+    let assets_dir = Path::new(server_name).join("assets");
+    let indexes_dir = assets_dir.join("indexes");
+    fs::create_dir_all(&indexes_dir).await?;
+    fs::write(
+        indexes_dir.join(format!("{}.json", asset_index.id)),
+        &index_bytes,
+    )
+    .await?;
+
+    println!(
+        "Asset index {}: {} objects, {} bytes",
+        asset_index.id,
+        index.objects.len(),
+        asset_index.totalSize
+    );
+
+    // Одинаковые объекты лежат под разными именами - качаем каждый хеш один раз
+    let mut tasks = BTreeMap::new();
+    // Копии для legacy-раскладок: объект -> путь
+    let mut copies = vec![];
+
+    for (name, object) in index.objects {
+        let prefix = object.hash[..2].to_owned();
+        let object_path = assets_dir.join("objects").join(&prefix).join(&object.hash);
+
+        if index.is_virtual {
+            copies.push((
+                object_path.clone(),
+                assets_dir.join("virtual").join(&asset_index.id).join(&name),
+            ));
+        }
+        if index.map_to_resources {
+            copies.push((
+                object_path.clone(),
+                Path::new(server_name).join("resources").join(&name),
+            ));
+        }
+
+        tasks
+            .entry(object.hash.clone())
+            .or_insert_with(|| DownloadTask {
+                url: format!("{}/{}/{}", RESOURCES_URL, prefix, object.hash),
+                name,
+                path: object_path,
+                // Имя объекта и есть его SHA-1
+                sha1: Some(object.hash),
+                size: Some(object.size),
+            });
+    }
+
+    download_all(tasks.into_values().collect()).await?;
+
+    for (object_path, copy) in copies {
+        fs::create_dir_all(copy.parent().unwrap()).await?;
+        fs::copy(&object_path, &copy).await?;
+    }
+
+    println!("✅ Assets saved to {:?}", assets_dir);
+
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{http::Validators, types::CacheLink, utils::sha1_hex};
+
+static CACHE: OnceLock<Option<Cache>> = OnceLock::new();
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Общий для всех серверов кеш файлов по SHA-1 (архивы Java без SHA-1 - по SHA-256):
+/// `<root>/objects/ab/abcdef...`
+/// и ответов с метаданными: `<root>/meta/<sha1 от URL>.{json,body}`
+pub struct Cache {
+    pub root: PathBuf,
+    pub link: CacheLink,
+}
+
+/// Вызывается один раз до первой загрузки. `None` - кеш выключен (`--no-cache`)
+pub fn configure(cache: Option<Cache>) {
+    if CACHE.set(cache).is_err() {
+        panic!("Cache already configured");
+    }
+}
+
+pub fn cache() -> Option<&'static Cache> {
+    CACHE.get_or_init(|| None).as_ref()
+}
+
+/// `$XDG_CACHE_HOME/neko-manifest`, `~/.cache/neko-manifest` или `%LOCALAPPDATA%\neko-manifest`
+pub fn default_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+
+    Some(base.join("neko-manifest"))
+}
+
+impl Cache {
+    /// `None` для строки, которая не похожа на SHA-1 или SHA-256
+    /// (например, мусор вместо `.sha1` из Maven)
+    fn object_path(&self, sha1: &str) -> Option<PathBuf> {
+        if !matches!(sha1.len(), 40 | 64) || !sha1.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let sha1 = sha1.to_ascii_lowercase();
+        Some(self.root.join("objects").join(&sha1[..2]).join(sha1))
+    }
+
+    /// Кладёт объект из кеша в `dest`. `false`, если такого объекта нет
+    pub async fn place(&self, sha1: &str, dest: &Path) -> io::Result<bool> {
+        let Some(object) = self.object_path(sha1) else {
+            return Ok(false);
+        };
+
+        if fs::metadata(&object).await.is_err() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Битый или старый файл на месте - иначе hard_link упадёт
+        if fs::metadata(dest).await.is_ok() {
+            fs::remove_file(dest).await?;
+        }
+
+        link_or_copy(&object, dest, self.link).await?;
+
+        // По времени изменения `cache prune` понимает, что объект ещё нужен
+        touch(object).await;
+
+        Ok(true)
+    }
+
+    /// Сохраняет проверенный файл в кеш, если его там ещё нет
+    pub async fn store(&self, sha1: &str, src: &Path) -> io::Result<()> {
+        let Some(object) = self.object_path(sha1) else {
+            return Ok(());
+        };
+
+        if fs::metadata(&object).await.is_ok() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(object.parent().unwrap()).await?;
+
+        // Через временное имя, чтобы параллельная загрузка не увидела недописанный объект
+        let tmp = tmp_path(&object);
+        link_or_copy(src, &tmp, self.link).await?;
+        fs::rename(&tmp, &object).await
+    }
+
+    /// Содержимое объекта, если он есть и не битый
+    pub async fn read_object(&self, sha1: &str) -> Option<Vec<u8>> {
+        let bytes = fs::read(self.object_path(sha1)?).await.ok()?;
+        sha1_hex(&bytes).eq_ignore_ascii_case(sha1).then_some(bytes)
+    }
+
+    pub async fn store_bytes(&self, sha1: &str, bytes: &[u8]) -> io::Result<()> {
+        let Some(object) = self.object_path(sha1) else {
+            return Ok(());
+        };
+
+        if fs::metadata(&object).await.is_ok() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(object.parent().unwrap()).await?;
+        write_atomic(&object, bytes).await
+    }
+
+    /// Удаляет объект, который не прошёл проверку после размещения
+    pub async fn evict(&self, sha1: &str) {
+        if let Some(object) = self.object_path(sha1) {
+            let _ = fs::remove_file(object).await;
+        }
+    }
+}
+
+/// Закешированный ответ на `http::get_bytes`
+pub struct CachedResponse {
+    pub validators: Validators,
+    pub body: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResponseHeaders {
+    url: String,
+    #[serde(flatten)]
+    validators: Validators,
+}
+
+impl Cache {
+    fn response_path(&self, url: &str) -> PathBuf {
+        self.root.join("meta").join(sha1_hex(url.as_bytes()))
+    }
+
+    pub async fn read_response(&self, url: &str) -> Option<CachedResponse> {
+        let path = self.response_path(url);
+        let headers: ResponseHeaders =
+            serde_json::from_slice(&fs::read(path.with_extension("json")).await.ok()?).ok()?;
+        let body = fs::read(path.with_extension("body")).await.ok()?;
+
+        // На 304 ответ не перезаписывается, а `cache prune` не должен его удалить
+        touch(path.with_extension("json")).await;
+        touch(path.with_extension("body")).await;
+
+        Some(CachedResponse {
+            validators: headers.validators,
+            body,
+        })
+    }
+
+    /// Сначала тело, потом заголовки: ответ без `.json` считается отсутствующим
+    pub async fn store_response(
+        &self,
+        url: &str,
+        validators: &Validators,
+        body: &[u8],
+    ) -> io::Result<()> {
+        let path = self.response_path(url);
+        fs::create_dir_all(path.parent().unwrap()).await?;
+
+        let headers = serde_json::to_vec_pretty(&ResponseHeaders {
+            url: url.to_owned(),
+            validators: validators.clone(),
+        })?;
+
+        write_atomic(&path.with_extension("body"), body).await?;
+        write_atomic(&path.with_extension("json"), &headers).await
+    }
+}
+
+async fn touch(path: PathBuf) {
+    let _ = tokio::task::spawn_blocking(move || {
+        std::fs::File::options()
+            .append(true)
+            .open(path)?
+            .set_modified(SystemTime::now())
+    })
+    .await;
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    PathBuf::from(tmp)
+}
+
+async fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    fs::write(&tmp, contents).await?;
+    fs::rename(&tmp, path).await
+}
+
+/// Hardlink и reflink возможны не везде (другой диск, ФС без CoW), тогда копируем
+async fn link_or_copy(src: &Path, dest: &Path, link: CacheLink) -> io::Result<()> {
+    let linked = match link {
+        CacheLink::Hardlink => fs::hard_link(src, dest).await.is_ok(),
+        CacheLink::Reflink => {
+            let (src, dest) = (src.to_path_buf(), dest.to_path_buf());
+            tokio::task::spawn_blocking(move || reflink(&src, &dest).is_ok()).await?
+        }
+        CacheLink::Copy => false,
+    };
+
+    if !linked {
+        fs::copy(src, dest).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src_file = std::fs::File::open(src)?;
+    let dest_file = std::fs::File::create(dest)?;
+
+    // FICLONE: btrfs, xfs, bcachefs и прочие ФС с copy-on-write
+    let result = unsafe { libc::ioctl(dest_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+
+    if result == -1 {
+        let error = io::Error::last_os_error();
+        drop(dest_file);
+        let _ = std::fs::remove_file(dest);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// `cache prune`: удаляет объекты и ответы из `meta/`, которые не использовались дольше `older_than`.
+/// С `None` очищает кеш полностью, включая Java для процессоров.
+/// Возвращает число файлов и освобождённые байты.
+pub fn prune(root: &Path, older_than: Option<Duration>) -> io::Result<(usize, u64)> {
+    let objects = root.join("objects");
+    let deadline = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+
+    let mut removed = 0;
+    let mut freed = 0;
+
+    // Java для процессоров Forge/NeoForge
+    let java = root.join("java");
+    if older_than.is_none() && java.exists() {
+        let (files, bytes) = tree_size(&java)?;
+        std::fs::remove_dir_all(&java)?;
+        removed += files;
+        freed += bytes;
+    }
+
+    let meta = root.join("meta");
+    if meta.exists() {
+        let (files, bytes) = prune_dir(&meta, deadline)?;
+        removed += files;
+        freed += bytes;
+    }
+
+    if !objects.exists() {
+        return Ok((removed, freed));
+    }
+
+    for bucket in std::fs::read_dir(&objects)? {
+        let bucket = bucket?.path();
+        if !bucket.is_dir() {
+            continue;
+        }
+
+        let (files, bytes) = prune_dir(&bucket, deadline)?;
+        removed += files;
+        freed += bytes;
+
+        if std::fs::read_dir(&bucket)?.next().is_none() {
+            std::fs::remove_dir(&bucket)?;
+        }
+    }
+
+    Ok((removed, freed))
+}
+
+/// Удаляет файлы старше `deadline` (все при `None`) и остатки прерванной записи
+fn prune_dir(dir: &Path, deadline: Option<SystemTime>) -> io::Result<(usize, u64)> {
+    let mut removed = 0;
+    let mut freed = 0;
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?.path();
+        let metadata = std::fs::metadata(&entry)?;
+
+        let expired = match deadline {
+            Some(deadline) => metadata.modified()? < deadline,
+            None => true,
+        };
+
+        // Остатки прерванных `store` удаляем всегда
+        let leftover = entry.extension().is_some_and(|ext| ext == "tmp");
+
+        if expired || leftover {
+            std::fs::remove_file(&entry)?;
+            removed += 1;
+            freed += metadata.len();
+        }
+    }
+
+    Ok((removed, freed))
+}
+
+fn tree_size(dir: &Path) -> io::Result<(usize, u64)> {
+    let mut files = 0;
+    let mut bytes = 0;
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.file_type()?;
+
+        if metadata.is_dir() {
+            let (inner_files, inner_bytes) = tree_size(&entry.path())?;
+            files += inner_files;
+            bytes += inner_bytes;
+        } else {
+            files += 1;
+            bytes += std::fs::symlink_metadata(entry.path())?.len();
+        }
+    }
+
+    Ok((files, bytes))
+}
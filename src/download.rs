@@ -0,0 +1,322 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures::future::join_all;
+use sha1::{Digest, Sha1};
+use tokio::{fs, io::AsyncReadExt, sync::Semaphore};
+
+use crate::{
+    cache::cache,
+    http::{download_to, get_text, is_offline, FetchError},
+    lock::{pinned_file, record_file},
+    types::Libraries,
+    utils::{hex, library_path},
+};
+
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+static PARALLELISM: AtomicUsize = AtomicUsize::new(8);
+
+/// Сколько файлов качается одновременно (`--parallel`)
+pub fn set_parallelism(parallelism: usize) {
+    PARALLELISM.store(parallelism.max(1), Ordering::Relaxed);
+}
+
+/// Один файл для общего загрузчика
+#[derive(Clone)]
+pub struct DownloadTask {
+    pub name: String,
+    pub url: String,
+    pub path: PathBuf,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
+}
+
+pub type DownloadError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Скачивает библиотеки в папку сервера, сверяя SHA-1 и размер.
+pub async fn download_libraries(
+    server_name: &str,
+    libs: &[Libraries],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tasks = Vec::new();
+
+    for lib in libs {
+        let path = Path::new(server_name).join(library_path(&lib.name));
+
+        if lib.url.is_empty() {
+            println!("[WHERE IS URL?] {}: {} -> {:?}", lib.name, lib.url, path);
+            continue;
+        }
+
+        tasks.push(DownloadTask {
+            name: lib.name.clone(),
+            url: lib.url.clone(),
+            path,
+            sha1: lib.sha1.clone().filter(|sha1| !sha1.is_empty()),
+            size: lib.size,
+        });
+    }
+
+    download_all(tasks).await
+}
+
+/// Чем закончилась задача, если без ошибки
+enum Outcome {
+    Downloaded,
+    /// Файл уже лежит на диске и совпал по SHA-1 и размеру
+    Reused,
+    /// Файл взят из общего кеша
+    Cached,
+}
+
+/// Качает файлы параллельно (не больше `--parallel` одновременно).
+/// Файлы, которые уже лежат на диске с нужным хешем, не перекачиваются.
+/// Битые файлы перекачиваются, а если не помогло - возвращается ошибка со списком всех битых артефактов.
+pub async fn download_all(tasks: Vec<DownloadTask>) -> Result<(), Box<dyn std::error::Error>> {
+    let semaphore = Arc::new(Semaphore::new(PARALLELISM.load(Ordering::Relaxed)));
+
+    let mut handles = vec![];
+    // Задачи с одним путём делили бы `.part` и мешали друг другу - качаем путь один раз
+    let mut paths = HashSet::new();
+
+    for task in tasks {
+        if !paths.insert(task.path.clone()) {
+            continue;
+        }
+
+        let permit = Arc::clone(&semaphore).acquire_owned().await.unwrap();
+
+        let handle = tokio::spawn(async move {
+            let _permit = permit; // удерживаем семафор до конца задачи
+
+            match fetch(&task).await {
+                Ok(Outcome::Downloaded) => {
+                    println!("{}: {} -> {:?}", task.name, task.url, task.path);
+                    Ok(Outcome::Downloaded)
+                }
+                Ok(outcome) => Ok(outcome),
+                Err(reason) => {
+                    eprintln!("❌ {}: {}", task.name, reason);
+                    Err(format!("{} ({}): {}", task.name, task.url, reason))
+                }
+            }
+        });
+
+        handles.push(handle);
+    }
+
+    let mut corrupted = Vec::new();
+    let mut downloaded = 0;
+    let mut reused = 0;
+    let mut cached = 0;
+
+    for res in join_all(handles).await {
+        match res? {
+            Ok(Outcome::Downloaded) => downloaded += 1,
+            Ok(Outcome::Reused) => reused += 1,
+            Ok(Outcome::Cached) => cached += 1,
+            Err(report) => corrupted.push(report),
+        }
+    }
+
+    println!(
+        "{} downloaded, {} reused, {} from cache",
+        downloaded, reused, cached
+    );
+
+    if !corrupted.is_empty() {
+        return Err(format!(
+            "{} artifact(s) failed verification:\n{}",
+            corrupted.len(),
+            corrupted.join("\n")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Файл уже скачан и совпадает с ожидаемым. Без SHA-1 проверить нечем, такой файл качаем заново
+/// (а в `--offline` берём тот, что есть).
+pub async fn is_present(task: &DownloadTask) -> bool {
+    if task.sha1.is_none() {
+        return is_offline() && fs::metadata(&task.path).await.is_ok();
+    }
+
+    match hash_file(&task.path).await {
+        Ok((sha1, size)) => verify(task, &sha1, size).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Качает один файл, если на диске его ещё нет (или он битый)
+pub async fn download_file(task: &DownloadTask) -> Result<(), DownloadError> {
+    fetch(task).await.map(|_| ())
+}
+
+/// Файл на диске -> общий кеш -> сеть. Скачанное сохраняется в кеш, а итоговый хеш - в neko.lock.
+async fn fetch(task: &DownloadTask) -> Result<Outcome, DownloadError> {
+    let task = &pin(task)?;
+
+    let outcome = if is_present(task).await {
+        Outcome::Reused
+    } else if restore_from_cache(task).await {
+        Outcome::Cached
+    } else {
+        download_verified(task).await?;
+
+        if let (Some(cache), Some(sha1)) = (cache(), &task.sha1) {
+            if let Err(e) = cache.store(sha1, &task.path).await {
+                eprintln!("[CACHE] Failed to store {}: {}", task.name, e);
+            }
+        }
+
+        Outcome::Downloaded
+    };
+
+    // У части старых библиотек хеша нет - считаем по скачанному файлу
+    match (&task.sha1, task.size) {
+        (Some(sha1), Some(size)) => record_file(&task.url, sha1, size),
+        _ => {
+            let (sha1, size) = hash_file(&task.path).await?;
+
+            // Найти такой файл в кеше можно будет только по хешу из neko.lock (`--locked`)
+            if let (Outcome::Downloaded, Some(cache)) = (&outcome, cache()) {
+                if let Err(e) = cache.store(&sha1, &task.path).await {
+                    eprintln!("[CACHE] Failed to store {}: {}", task.name, e);
+                }
+            }
+
+            record_file(&task.url, &sha1, size);
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// С `--locked` хеш и размер берутся из neko.lock, а файлы вне lock-файла не качаются
+fn pin(task: &DownloadTask) -> Result<DownloadTask, FetchError> {
+    match pinned_file(&task.url).map_err(|e| FetchError::from_lock(&task.url, e))? {
+        Some(pinned) => Ok(DownloadTask {
+            sha1: Some(pinned.sha1.clone()),
+            size: Some(pinned.size),
+            ..task.clone()
+        }),
+        None => Ok(task.clone()),
+    }
+}
+
+/// Объект из кеша проверяется так же, как файл на диске: битый удаляется из кеша
+pub async fn restore_from_cache(task: &DownloadTask) -> bool {
+    let (Some(cache), Some(sha1)) = (cache(), &task.sha1) else {
+        return false;
+    };
+
+    match cache.place(sha1, &task.path).await {
+        Ok(true) if is_present(task).await => true,
+        Ok(true) => {
+            eprintln!("[CACHE] Corrupted object for {}, downloading", task.name);
+            cache.evict(sha1).await;
+            let _ = fs::remove_file(&task.path).await;
+            false
+        }
+        Ok(false) => false,
+        Err(e) => {
+            eprintln!("[CACHE] Failed to place {}: {}", task.name, e);
+            false
+        }
+    }
+}
+
+/// Тело пишется потоком в `<файл>.part` и переименовывается только после проверки,
+/// так что под итоговым именем никогда не лежит недокачанный файл.
+/// Повторы при сетевых ошибках делает `http`, здесь перекачиваем только не прошедшие проверку файлы.
+pub async fn download_verified(task: &DownloadTask) -> Result<(), DownloadError> {
+    if let Some(parent) = task.path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let part = part_path(&task.path);
+    let mut reason = String::new();
+
+    for attempt in 1..=DOWNLOAD_ATTEMPTS {
+        if attempt > 1 {
+            println!(
+                "[RETRY {}/{}] {}: {}",
+                attempt, DOWNLOAD_ATTEMPTS, task.name, reason
+            );
+        }
+
+        download_to(&task.url, &part).await?;
+
+        let (sha1, size) = hash_file(&part).await?;
+
+        if let Err(e) = verify(task, &sha1, size) {
+            reason = e;
+            fs::remove_file(&part).await?;
+            continue;
+        }
+
+        fs::rename(&part, &task.path).await?;
+
+        return Ok(());
+    }
+
+    Err(reason.into())
+}
+
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// SHA-1 и размер файла без чтения его целиком в память
+pub async fn hash_file(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha1::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok((hex(&hasher.finalize()), size))
+}
+
+fn verify(task: &DownloadTask, sha1: &str, size: u64) -> Result<(), String> {
+    if let Some(expected) = task.size {
+        if size != expected {
+            return Err(format!("expected {} bytes, got {}", expected, size));
+        }
+    }
+
+    if let Some(expected) = &task.sha1 {
+        if !sha1.eq_ignore_ascii_case(expected) {
+            return Err(format!("expected sha1 {}, got {}", expected, sha1));
+        }
+    }
+
+    Ok(())
+}
+
+/// SHA-1 из `.sha1` рядом с артефактом в Maven-репозитории
+pub async fn fetch_maven_sha1(url: &str) -> Option<String> {
+    let body = get_text(&format!("{}.sha1", url)).await.ok()?;
+
+    // Некоторые репозитории пишут "<hash>  <file>"
+    body.split_whitespace().next().map(|sha1| sha1.to_owned())
+}
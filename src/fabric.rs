@@ -1,10 +1,11 @@
-use std::{collections::HashSet, path::Path};
-
-use tokio::{fs::File, io::AsyncWriteExt};
+use std::collections::{BTreeMap, HashSet};
 
 use crate::{
+    download::{download_libraries, fetch_maven_sha1},
+    http::get_json,
+    manifest::write_manifest,
     mojang::parse_mojang,
-    types::{FabricManifest, Libraries, LibraryObject, NekoManifest, OsType},
+    types::{FabricManifest, Libraries, LibraryObject, NekoManifest, Platform},
     utils::{default_jvm_args, resolve_maven},
 };
 
@@ -13,97 +14,87 @@ pub async fn create_fabric_manifest(
     fabric_version: String,
     mc_version: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mojang_parsed = parse_mojang(mc_version.clone()).await;
-
-    let fabric_manifest = reqwest::get(format!(
+    let fabric_manifest: FabricManifest = get_json(&format!(
         "https://meta.fabricmc.net/v2/versions/loader/{}/{}/profile/json",
         mc_version, fabric_version
     ))
-    .await?
-    .json::<FabricManifest>()
-    .await
-    .unwrap();
-
-    let mut libraries = HashSet::new();
-
-    libraries.extend(mojang_parsed.hash_libs);
+    .await?;
 
-    for lib in &fabric_manifest.libraries {
-        let lib_obj = LibraryObject {
-            path: format!("libraries/{}", resolve_maven(&lib.name)),
-            os: vec![OsType::Windows, OsType::Linux, OsType::MacOs],
-        };
+    create_profile_manifest(server_name, mc_version, fabric_manifest).await
+}
 
-        libraries.insert(lib_obj);
-    }
+/// Общая часть для лоадеров с fabric-подобным профилем (Fabric, Quilt)
+pub async fn create_profile_manifest(
+    server_name: String,
+    mc_version: String,
+    fabric_manifest: FabricManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mojang_parsed = parse_mojang(mc_version).await?;
 
-    let fabric_libs = fabric_manifest.libraries.into_iter().map(|lib| Libraries {
-        name: lib.name.clone(),
-        url: format!("{}{}", lib.url, resolve_maven(&lib.name)),
-        sha1: Some("".to_string()),
-    });
+    let mut libraries = HashSet::new();
 
-    let libs = mojang_parsed
-        .libraries
-        .into_iter()
-        .chain(fabric_libs)
-        .collect::<Vec<Libraries>>();
+    libraries.extend(mojang_parsed.hash_libs);
 
-    for lib in &libs {
-        let normal_path = resolve_maven(&lib.name);
+    let mut libs = mojang_parsed.libraries;
 
-        let path = format!("{}/{}", server_name, normal_path);
+    for lib in fabric_manifest.libraries {
+        let url = format!("{}{}", lib.url, resolve_maven(&lib.name));
 
-        let file_path: &Path = Path::new(&path).parent().unwrap();
+        // Fabric meta не всегда отдаёт sha1, тогда берём его из Maven
+        let sha1 = match lib.sha1 {
+            Some(sha1) => Some(sha1),
+            None => fetch_maven_sha1(&url).await,
+        };
 
-        if !file_path.exists() {
-            std::fs::create_dir_all(file_path).unwrap();
+        if sha1.is_none() {
+            println!("[NO SHA1] {}: {}", lib.name, url);
         }
 
-        let res = reqwest::get(&lib.url)
-            .await?
-            .bytes()
-            .await
-            .expect("Failed to get file");
-
-        let mut file = File::create(&path).await.expect("Failed to create file");
-
-        file.write_all(&res).await?;
-
-        file.flush().await?;
-
-        println!("{}: {} -> {:?}", lib.name, lib.url, file_path);
+        libraries.insert(LibraryObject {
+            sha1: sha1.clone(),
+            size: lib.size,
+            ..LibraryObject::new(
+                format!("libraries/{}", resolve_maven(&lib.name)),
+                Platform::ALL.to_vec(),
+            )
+        });
+
+        libs.push(Libraries {
+            name: lib.name,
+            url,
+            sha1,
+            size: lib.size,
+        });
     }
 
-    let mut manifest = File::create(format!("{}/manifest.json", server_name))
-        .await
-        .expect("Failed to create manifest");
+    download_libraries(&server_name, &libs).await?;
 
     let mut jvm = default_jvm_args();
 
+    jvm.extend(mojang_parsed.jvm);
     jvm.extend(fabric_manifest.arguments.jvm);
 
+    let mut game = mojang_parsed.game;
+
+    game.extend(fabric_manifest.arguments.game);
+
     let neko_manifest = NekoManifest {
         mainclass: fabric_manifest.mainClass,
         assetIndex: mojang_parsed.asset_index,
         libraries,
         jvm,
-        game: fabric_manifest.arguments.game,
+        game,
+        jvmRules: mojang_parsed.jvm_rules,
+        gameRules: mojang_parsed.game_rules,
         verify: vec![
             "mods".to_string(),
             "libraries".to_string(),
             "minecraft.jar".to_string(),
         ],
         ignore: vec!["options.txt".to_string()],
+        files: BTreeMap::new(),
+        java: BTreeMap::new(),
     };
 
-    let manifest_json =
-        serde_json::to_string(&neko_manifest).expect("Failed to serialize manifest");
-
-    manifest
-        .write_all(manifest_json.as_bytes())
-        .await
-        .expect("Failed to write manifest to file");
-
-    Ok(())
+    write_manifest(&server_name, neko_manifest).await
 }
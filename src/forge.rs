@@ -1,20 +1,23 @@
-use std::collections::HashSet;
-use std::path::Path;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use std::collections::{BTreeMap, HashSet};
 
+use crate::download::download_libraries;
+use crate::manifest::write_manifest;
 use crate::mojang::parse_mojang;
-use crate::types::{LibraryObject, OsType};
+use crate::processors::install_modern_loader;
+use crate::types::{LibraryObject, Platform};
 
-use crate::utils::{default_jvm_args, run_loader_installer};
-use crate::{resolve_maven, utils::get_loader_install_profile, NekoManifest};
+use crate::utils::{default_jvm_args, install_legacy_loader, merge_legacy_arguments};
+use crate::{
+    utils::{get_loader_install_profile, resolve_maven},
+    NekoManifest,
+};
 
 pub async fn create_forge_manifest(
     server_name: String,
     loader_version: String,
     mc_version: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mojang_parsed = parse_mojang(mc_version.clone()).await;
+    let mojang_parsed = parse_mojang(mc_version.clone()).await?;
 
     let forge_manifest = get_loader_install_profile("forge", &mc_version, &loader_version).await?;
 
@@ -22,13 +25,18 @@ pub async fn create_forge_manifest(
 
     libraries.extend(mojang_parsed.hash_libs);
 
-    for lib in forge_manifest.libraries {
+    for lib in &forge_manifest.libraries {
         if lib.downloads.is_none() {
             continue;
         }
+        let artifact = &lib.downloads.as_ref().unwrap().artifact;
         let lib_obj = LibraryObject {
-            path: format!("libraries/{}", lib.downloads.unwrap().artifact.path),
-            os: vec![OsType::Windows, OsType::Linux, OsType::MacOs],
+            sha1: Some(artifact.sha1.clone()).filter(|sha1| !sha1.is_empty()),
+            size: artifact.size,
+            ..LibraryObject::new(
+                format!("libraries/{}", artifact.path),
+                Platform::ALL.to_vec(),
+            )
         };
 
         let maven = resolve_maven(&lib.name);
@@ -40,72 +48,52 @@ pub async fn create_forge_manifest(
         }
     }
 
-    for lib in mojang_parsed.libraries {
-        let normal_path = resolve_maven(&lib.name);
-
-        let path = format!("{}/{}", server_name, normal_path);
-
-        let file_path: &Path = Path::new(&path).parent().unwrap();
-
-        if !file_path.exists() {
-            std::fs::create_dir_all(file_path).unwrap();
-        }
-
-        if lib.url.len() == 0 {
-            println!(
-                "[WHERE IS URL?] {}: {} -> {:?}",
-                lib.name, lib.url, file_path
-            );
-            continue;
-        }
-
-        let res = reqwest::get(&lib.url)
-            .await?
-            .bytes()
-            .await
-            .expect("Failed to get file");
-
-        let mut file = File::create(&path).await.expect("Failed to create file");
-
-        file.write_all(&res).await?;
-
-        file.flush().await?;
-
-        println!("{}: {} -> {:?}", lib.name, lib.url, file_path);
+    download_libraries(&server_name, &mojang_parsed.libraries).await?;
+
+    if forge_manifest.legacy.is_some() {
+        install_legacy_loader(&mc_version, &loader_version, &server_name, &forge_manifest).await?;
+    } else {
+        install_modern_loader(
+            "forge",
+            &mc_version,
+            &loader_version,
+            &server_name,
+            &forge_manifest,
+        )
+        .await?;
     }
 
-    run_loader_installer("forge", mc_version, loader_version, server_name.clone()).await;
-
-    let mut manifest = File::create(format!("{}/manifest.json", server_name))
-        .await
-        .expect("Failed to create manifest");
-
-    
     let mut jvm = default_jvm_args();
 
+    jvm.extend(mojang_parsed.jvm);
     jvm.extend(forge_manifest.arguments.jvm);
 
+    // launchwrapper заменяет ванильные аргументы своими, новые версии только дополняют их
+    let game = if forge_manifest.minecraftArguments.is_some() {
+        merge_legacy_arguments(mojang_parsed.game, forge_manifest.arguments.game)
+    } else {
+        let mut game = mojang_parsed.game;
+        game.extend(forge_manifest.arguments.game);
+        game
+    };
+
     let neko_manifest = NekoManifest {
         mainclass: forge_manifest.mainClass,
         assetIndex: mojang_parsed.asset_index,
         libraries,
         jvm,
-        game: forge_manifest.arguments.game,
+        game,
+        jvmRules: mojang_parsed.jvm_rules,
+        gameRules: mojang_parsed.game_rules,
         verify: vec![
             "mods".to_string(),
             "libraries".to_string(),
             "minecraft.jar".to_string(),
         ],
         ignore: vec!["options.txt".to_string()],
+        files: BTreeMap::new(),
+        java: BTreeMap::new(),
     };
 
-    let manifest_json =
-        serde_json::to_string(&neko_manifest).expect("Failed to serialize manifest");
-
-    manifest
-        .write_all(manifest_json.as_bytes())
-        .await
-        .expect("Failed to write manifest to file");
-
-    Ok(())
+    write_manifest(&server_name, neko_manifest).await
 }
@@ -1,16 +1,25 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct ForgeClientManifest {
     pub id: String,
+    #[serde(default)]
     pub inheritsFrom: String,
     pub mainClass: String,
     pub libraries: Vec<ForgeLibrary>,
+    #[serde(default)]
     pub arguments: ForgeArguments,
+    // Forge до 1.13 (launchwrapper) передаёт аргументы игры одной строкой
+    pub minecraftArguments: Option<String>,
+    // Заполняется только для установщиков старого формата (без version.json)
+    #[serde(skip)]
+    pub legacy: Option<LegacyInstall>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct ForgeArguments {
     pub game: Vec<String>,
     pub jvm: Vec<String>,
@@ -34,3 +43,71 @@ pub struct ForgeArtifact {
     pub sha1: String,
     pub size: Option<u64>,
 }
+
+// install_profile.json установщиков 1.13+: что скачать и какие процессоры запустить
+#[derive(Debug, Deserialize)]
+pub struct InstallProfile {
+    #[serde(default)]
+    pub data: HashMap<String, SidedData>,
+    #[serde(default)]
+    pub processors: Vec<Processor>,
+    #[serde(default)]
+    pub libraries: Vec<ForgeLibrary>,
+}
+
+// Серверное значение нам не нужно, собираем только клиент
+#[derive(Debug, Deserialize)]
+pub struct SidedData {
+    pub client: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Processor {
+    /// Maven-координаты jar с Main-Class
+    pub jar: String,
+    #[serde(default)]
+    pub classpath: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Файл -> ожидаемый SHA-1, оба могут быть `{KEY}` из `data`
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+    /// Без `sides` процессор нужен и клиенту, и серверу
+    pub sides: Option<Vec<String>>,
+}
+
+// Legacy installer (1.7.10 - 1.12.2): install_profile.json с versionInfo
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct LegacyInstallProfile {
+    pub install: LegacyInstall,
+    pub versionInfo: LegacyVersionInfo,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[allow(non_snake_case)]
+pub struct LegacyInstall {
+    /// Maven-координаты universal jar
+    pub path: String,
+    /// Имя universal jar внутри установщика
+    pub filePath: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct LegacyVersionInfo {
+    pub id: String,
+    #[serde(default)]
+    pub inheritsFrom: String,
+    pub mainClass: String,
+    pub minecraftArguments: String,
+    pub libraries: Vec<LegacyLibrary>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegacyLibrary {
+    pub name: String,
+    pub url: Option<String>,
+    pub checksums: Option<Vec<String>>,
+    pub natives: Option<serde_json::Value>,
+}
@@ -0,0 +1,874 @@
+use std::{fmt, future::Future, path::Path, sync::OnceLock, time::Duration};
+
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE},
+    Client, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    cache::cache,
+    lock::{self, LockError, Resolved, LOCK_FILE},
+};
+
+static SETTINGS: OnceLock<HttpSettings> = OnceLock::new();
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Таймауты и повторы для всех запросов (`--retries`, `--connect-timeout`, `--read-timeout`)
+#[derive(Clone, Debug)]
+pub struct HttpSettings {
+    pub retries: u32,
+    pub connect_timeout: Duration,
+    /// Сколько ждать заголовков ответа или очередного куска тела
+    pub read_timeout: Duration,
+    /// Первая пауза перед повтором, дальше удваивается
+    pub backoff: Duration,
+    /// Зеркала (`--mirror`), пробуются до оригинального адреса
+    pub mirrors: Vec<Mirror>,
+    /// `--offline`: никаких запросов, только кеш
+    pub offline: bool,
+}
+
+/// Подмена начала адреса: `https://libraries.minecraft.net` -> `https://bmclapi2.bangbang93.com/maven`
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    pub origin: String,
+    pub mirror: String,
+}
+
+/// Адреса BMCLAPI для `--mirror bmclapi`
+const BMCLAPI: [(&str, &str); 10] = [
+    (
+        "https://piston-meta.mojang.com",
+        "https://bmclapi2.bangbang93.com",
+    ),
+    (
+        "https://piston-data.mojang.com",
+        "https://bmclapi2.bangbang93.com",
+    ),
+    (
+        "https://launchermeta.mojang.com",
+        "https://bmclapi2.bangbang93.com",
+    ),
+    (
+        "https://launcher.mojang.com",
+        "https://bmclapi2.bangbang93.com",
+    ),
+    (
+        "https://resources.download.minecraft.net",
+        "https://bmclapi2.bangbang93.com/assets",
+    ),
+    (
+        "https://libraries.minecraft.net",
+        "https://bmclapi2.bangbang93.com/maven",
+    ),
+    (
+        "https://maven.minecraftforge.net",
+        "https://bmclapi2.bangbang93.com/maven",
+    ),
+    (
+        "https://maven.neoforged.net/releases",
+        "https://bmclapi2.bangbang93.com/maven",
+    ),
+    (
+        "https://maven.fabricmc.net",
+        "https://bmclapi2.bangbang93.com/maven",
+    ),
+    (
+        "https://meta.fabricmc.net",
+        "https://bmclapi2.bangbang93.com/fabric-meta",
+    ),
+];
+
+/// Разбирает `--mirror`: `ORIGIN=MIRROR` или готовый набор `bmclapi`
+pub fn parse_mirror(spec: &str) -> Result<Vec<Mirror>, String> {
+    if spec == "bmclapi" {
+        return Ok(BMCLAPI
+            .iter()
+            .map(|(origin, mirror)| Mirror {
+                origin: origin.to_string(),
+                mirror: mirror.to_string(),
+            })
+            .collect());
+    }
+
+    let (origin, mirror) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid mirror {}, expected ORIGIN=MIRROR or bmclapi", spec))?;
+
+    Ok(vec![Mirror {
+        origin: origin.trim_end_matches('/').to_owned(),
+        mirror: mirror.trim_end_matches('/').to_owned(),
+    }])
+}
+
+/// Адрес на зеркале (самое длинное совпавшее начало), если оно настроено
+fn mirrored(settings: &HttpSettings, url: &str) -> Option<String> {
+    settings
+        .mirrors
+        .iter()
+        .filter(|m| {
+            url.strip_prefix(m.origin.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|m| m.origin.len())
+        .map(|m| format!("{}{}", m.mirror, &url[m.origin.len()..]))
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            retries: 4,
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            backoff: Duration::from_millis(500),
+            mirrors: Vec::new(),
+            offline: false,
+        }
+    }
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Вызывается один раз до первого запроса
+pub fn configure(settings: HttpSettings) {
+    SETTINGS.set(settings).expect("HTTP settings already set");
+}
+
+fn settings() -> &'static HttpSettings {
+    SETTINGS.get_or_init(HttpSettings::default)
+}
+
+pub fn is_offline() -> bool {
+    settings().offline
+}
+
+/// Один клиент на весь запуск, чтобы переиспользовать соединения
+pub fn client() -> &'static Client {
+    CLIENT.get_or_init(|| build_client(settings()))
+}
+
+fn build_client(settings: &HttpSettings) -> Client {
+    Client::builder()
+        .user_agent(concat!("neko-manifest-cli/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(settings.connect_timeout)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+#[derive(Debug)]
+pub enum FetchErrorKind {
+    Status(StatusCode),
+    Timeout,
+    Transport(String),
+    /// Не удалось записать на диск, повтор не поможет
+    Io(String),
+    /// `--offline`, а в кеше ответа нет
+    Offline,
+    /// `--locked`, а URL нет в neko.lock
+    NotLocked,
+    /// `--locked`, а ответ отличается от закреплённого
+    LockMismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl FetchErrorKind {
+    fn is_retryable(&self) -> bool {
+        match self {
+            FetchErrorKind::Status(status) => {
+                *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error()
+            }
+            FetchErrorKind::Timeout | FetchErrorKind::Transport(_) => true,
+            FetchErrorKind::Io(_)
+            | FetchErrorKind::Offline
+            | FetchErrorKind::NotLocked
+            | FetchErrorKind::LockMismatch { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchError {
+    pub url: String,
+    pub kind: FetchErrorKind,
+}
+
+impl FetchError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self.kind {
+            FetchErrorKind::Status(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Ресурс не искали в сети из-за `--offline` или `--locked`
+    pub fn is_miss(&self) -> bool {
+        matches!(
+            self.kind,
+            FetchErrorKind::Offline | FetchErrorKind::NotLocked
+        )
+    }
+
+    pub fn from_lock(url: &str, error: LockError) -> FetchError {
+        FetchError {
+            url: url.to_owned(),
+            kind: match error {
+                LockError::Missing => FetchErrorKind::NotLocked,
+                LockError::Mismatch { expected, actual } => {
+                    FetchErrorKind::LockMismatch { expected, actual }
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FetchErrorKind::Status(status) => write!(f, "{}: HTTP {}", self.url, status),
+            FetchErrorKind::Timeout => write!(f, "{}: timed out", self.url),
+            FetchErrorKind::Transport(e) | FetchErrorKind::Io(e) => {
+                write!(f, "{}: {}", self.url, e)
+            }
+            FetchErrorKind::Offline => {
+                write!(
+                    f,
+                    "{}: not in cache, cannot fetch in offline mode",
+                    self.url
+                )
+            }
+            FetchErrorKind::NotLocked => write!(f, "{}: not in {}", self.url, LOCK_FILE),
+            FetchErrorKind::LockMismatch { expected, actual } => write!(
+                f,
+                "{}: changed since {} was written (expected sha1 {}, got {})",
+                self.url, LOCK_FILE, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Заголовки для повторной проверки закешированного ответа
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+enum Fetched {
+    Body(Vec<u8>, Validators),
+    /// 304: закешированный ответ всё ещё актуален
+    NotModified,
+}
+
+/// Метаданные: каждый ответ сверяется с neko.lock (`--locked`) и попадает в новый lock-файл.
+/// Точные байты ещё и кладутся в кеш по SHA-1, чтобы `--locked` мог собрать то же самое,
+/// даже если источник потом перевыложил файл.
+pub async fn get_bytes(url: &str) -> Result<Vec<u8>, FetchError> {
+    let pinned = lock::pinned_metadata(url).map_err(|e| FetchError::from_lock(url, e))?;
+
+    let locked_body = match (pinned, cache()) {
+        (Some(pinned), Some(cache)) => cache.read_object(&pinned.sha1).await,
+        _ => None,
+    };
+
+    let body = match locked_body {
+        Some(body) => body,
+        None => fetch_metadata(url).await?,
+    };
+
+    let sha1 = lock::check_metadata(url, &body).map_err(|e| FetchError::from_lock(url, e))?;
+
+    if let Some(cache) = cache() {
+        if let Err(e) = cache.store_bytes(&sha1, &body).await {
+            eprintln!("[CACHE] Failed to store {}: {}", url, e);
+        }
+    }
+
+    Ok(body)
+}
+
+/// Ответ сохраняется в кеш и перепроверяется через ETag/Last-Modified.
+/// С `--offline` берётся только из кеша, а если сеть недоступна - из кеша с предупреждением.
+async fn fetch_metadata(url: &str) -> Result<Vec<u8>, FetchError> {
+    let settings = settings();
+    let cached = match cache() {
+        Some(cache) => cache.read_response(url).await,
+        None => None,
+    };
+
+    if settings.offline {
+        return cached.map(|cached| cached.body).ok_or_else(|| FetchError {
+            url: url.to_owned(),
+            kind: FetchErrorKind::Offline,
+        });
+    }
+
+    let validators = cached.as_ref().map(|cached| &cached.validators);
+
+    match fetch_with(client(), settings, url, validators).await {
+        Ok(Fetched::Body(body, validators)) => {
+            if let Some(cache) = cache() {
+                if let Err(e) = cache.store_response(url, &validators, &body).await {
+                    eprintln!("[CACHE] Failed to store {}: {}", url, e);
+                }
+            }
+            Ok(body)
+        }
+        // 304 приходит только на запрос с заголовками из кеша
+        Ok(Fetched::NotModified) => Ok(cached.map(|cached| cached.body).unwrap_or_default()),
+        Err(e) => match cached {
+            Some(cached) => {
+                eprintln!("[CACHE] {}, using cached response", e);
+                Ok(cached.body)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+pub async fn get_text(url: &str) -> Result<String, FetchError> {
+    let bytes = get_bytes(url).await?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, Box<dyn std::error::Error>> {
+    let bytes = get_bytes(url).await?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", url, e).into())
+}
+
+/// Индекс, который меняется с каждым релизом (`version_manifest_v2.json`, `all.json`,
+/// "latest" Adoptium и Zulu). Ответ не сверяется с neko.lock и не попадает в него
+pub async fn get_index_bytes(url: &str) -> Result<Vec<u8>, FetchError> {
+    fetch_metadata(url).await
+}
+
+pub async fn get_index<T: DeserializeOwned>(url: &str) -> Result<T, Box<dyn std::error::Error>> {
+    let bytes = get_index_bytes(url).await?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", url, e).into())
+}
+
+/// Выбор по индексу: с `--locked` берётся из neko.lock без запроса к индексу,
+/// иначе его делает `lookup`, а результат записывается в новый lock-файл
+pub async fn resolve<F, Fut>(
+    key: &str,
+    lookup: F,
+) -> Result<Option<Resolved>, Box<dyn std::error::Error>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Option<Resolved>, Box<dyn std::error::Error>>>,
+{
+    let resolved = match lock::pinned_resolved(key).map_err(|e| FetchError::from_lock(key, e))? {
+        Some(resolved) => resolved.clone(),
+        None => lookup().await?,
+    };
+
+    lock::record_resolved(key, resolved.clone());
+
+    Ok(resolved)
+}
+
+/// Качает файл потоком в `part`. Если `part` уже есть (прерванная загрузка), докачивает
+/// остаток через Range, а сервер без поддержки Range просто отдаст файл целиком.
+pub async fn download_to(url: &str, part: &Path) -> Result<(), FetchError> {
+    if settings().offline {
+        return Err(FetchError {
+            url: url.to_owned(),
+            kind: FetchErrorKind::Offline,
+        });
+    }
+
+    stream_with(client(), settings(), url, part).await
+}
+
+async fn fetch_with(
+    client: &Client,
+    settings: &HttpSettings,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<Fetched, FetchError> {
+    with_mirrors(settings, url, |url| async move {
+        with_retries(settings, &url, || {
+            fetch_once(client, settings, &url, validators)
+        })
+        .await
+    })
+    .await
+}
+
+async fn stream_with(
+    client: &Client,
+    settings: &HttpSettings,
+    url: &str,
+    part: &Path,
+) -> Result<(), FetchError> {
+    with_mirrors(settings, url, |url| async move {
+        with_retries(settings, &url, || stream_once(client, settings, &url, part)).await
+    })
+    .await
+}
+
+/// Сначала зеркало, а если оно не справилось - оригинальный адрес
+async fn with_mirrors<T, F, Fut>(
+    settings: &HttpSettings,
+    url: &str,
+    mut request: F,
+) -> Result<T, FetchError>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T, FetchError>>,
+{
+    if let Some(mirror) = mirrored(settings, url) {
+        match request(mirror).await {
+            Ok(result) => return Ok(result),
+            Err(e) => eprintln!("[MIRROR FAILED] {}, falling back to {}", e, url),
+        }
+    }
+
+    request(url.to_owned()).await
+}
+
+/// Повторяет запрос с экспоненциальной паузой, пока ошибка временная (таймаут, обрыв, 408, 429, 5xx)
+async fn with_retries<T, F, Fut>(
+    settings: &HttpSettings,
+    url: &str,
+    mut request: F,
+) -> Result<T, FetchError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, FetchErrorKind>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        let kind = match request().await {
+            Ok(result) => return Ok(result),
+            Err(kind) => kind,
+        };
+
+        if !kind.is_retryable() || attempt >= settings.retries {
+            return Err(FetchError {
+                url: url.to_owned(),
+                kind,
+            });
+        }
+
+        let delay = settings
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF);
+
+        attempt += 1;
+
+        eprintln!(
+            "[HTTP RETRY {}/{}] {}: {:?}, waiting {:?}",
+            attempt, settings.retries, url, kind, delay
+        );
+
+        sleep(delay).await;
+    }
+}
+
+async fn fetch_once(
+    client: &Client,
+    settings: &HttpSettings,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<Fetched, FetchErrorKind> {
+    let mut request = client.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let mut response = match timeout(settings.read_timeout, request.send()).await {
+        Err(_) => return Err(FetchErrorKind::Timeout),
+        Ok(Err(e)) => return Err(FetchErrorKind::Transport(e.to_string())),
+        Ok(Ok(response)) => response,
+    };
+
+    if response.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(Fetched::NotModified);
+    }
+
+    if !response.status().is_success() {
+        return Err(FetchErrorKind::Status(response.status()));
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let validators = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    let mut body = Vec::new();
+
+    loop {
+        match timeout(settings.read_timeout, response.chunk()).await {
+            Err(_) => return Err(FetchErrorKind::Timeout),
+            Ok(Err(e)) => return Err(FetchErrorKind::Transport(e.to_string())),
+            Ok(Ok(Some(chunk))) => body.extend_from_slice(&chunk),
+            Ok(Ok(None)) => return Ok(Fetched::Body(body, validators)),
+        }
+    }
+}
+
+async fn stream_once(
+    client: &Client,
+    settings: &HttpSettings,
+    url: &str,
+    part: &Path,
+) -> Result<(), FetchErrorKind> {
+    let offset = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut response = match timeout(settings.read_timeout, request.send()).await {
+        Err(_) => return Err(FetchErrorKind::Timeout),
+        Ok(Err(e)) => return Err(FetchErrorKind::Transport(e.to_string())),
+        Ok(Ok(response)) => response,
+    };
+
+    // Кусок на диске длиннее файла на сервере - начинаем заново
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        fs::remove_file(part)
+            .await
+            .map_err(|e| FetchErrorKind::Io(e.to_string()))?;
+        return Err(FetchErrorKind::Transport(
+            "stale partial download, restarting".to_owned(),
+        ));
+    }
+
+    if !response.status().is_success() {
+        return Err(FetchErrorKind::Status(response.status()));
+    }
+
+    let file = if response.status() == StatusCode::PARTIAL_CONTENT {
+        OpenOptions::new().append(true).open(part).await
+    } else {
+        fs::File::create(part).await
+    };
+    let mut file = file.map_err(|e| FetchErrorKind::Io(e.to_string()))?;
+
+    loop {
+        match timeout(settings.read_timeout, response.chunk()).await {
+            Err(_) => return Err(FetchErrorKind::Timeout),
+            Ok(Err(e)) => return Err(FetchErrorKind::Transport(e.to_string())),
+            Ok(Ok(Some(chunk))) => file
+                .write_all(&chunk)
+                .await
+                .map_err(|e| FetchErrorKind::Io(e.to_string()))?,
+            Ok(Ok(None)) => break,
+        }
+    }
+
+    file.flush()
+        .await
+        .map_err(|e| FetchErrorKind::Io(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Что отвечает тестовый сервер на запрос с данным номером
+    #[derive(Clone, Copy)]
+    enum Reply {
+        Status(u16),
+        Hang,
+        /// 200 с полным Content-Length, но соединение рвётся после первых n байт `BODY`
+        Cut(usize),
+        /// 206 с остатком `BODY` начиная с байта из заголовка Range
+        Ranged,
+        /// 304, если пришёл `If-None-Match: "v1"`, иначе 200 с этим ETag
+        Etag,
+    }
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    fn requested_offset(request: &str) -> usize {
+        request
+            .lines()
+            .find_map(|line| {
+                line.to_ascii_lowercase()
+                    .strip_prefix("range: bytes=")
+                    .map(str::to_owned)
+            })
+            .and_then(|range| range.trim_end_matches('-').parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Локальный HTTP-сервер: отвечает по сценарию, последний ответ повторяется
+    async fn stand_in(script: Vec<Reply>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                let reply = script[hit.min(script.len() - 1)];
+
+                tokio::spawn(async move {
+                    let mut request = [0u8; 1024];
+                    let read = socket.read(&mut request).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..read]).into_owned();
+
+                    match reply {
+                        Reply::Hang => sleep(Duration::from_secs(5)).await,
+                        Reply::Status(code) => {
+                            let body = if code == 200 { "ok" } else { "fail" };
+                            let response = format!(
+                                "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                code,
+                                body.len(),
+                                body
+                            );
+                            let _ = socket.write_all(response.as_bytes()).await;
+                        }
+                        Reply::Cut(sent) => {
+                            let head = format!(
+                                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                BODY.len()
+                            );
+                            let _ = socket.write_all(head.as_bytes()).await;
+                            let _ = socket.write_all(&BODY[..sent]).await;
+                        }
+                        Reply::Etag => {
+                            let response = if request
+                                .to_ascii_lowercase()
+                                .contains("if-none-match: \"v1\"")
+                            {
+                                "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n"
+                            } else {
+                                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                            };
+                            let _ = socket.write_all(response.as_bytes()).await;
+                        }
+                        Reply::Ranged => {
+                            let offset = requested_offset(&request);
+                            let head = format!(
+                                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                offset,
+                                BODY.len() - 1,
+                                BODY.len(),
+                                BODY.len() - offset
+                            );
+                            let _ = socket.write_all(head.as_bytes()).await;
+                            let _ = socket.write_all(&BODY[offset..]).await;
+                        }
+                    }
+                });
+            }
+        });
+
+        (url, hits)
+    }
+
+    fn test_settings(retries: u32) -> HttpSettings {
+        HttpSettings {
+            retries,
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_millis(200),
+            backoff: Duration::from_millis(10),
+            ..HttpSettings::default()
+        }
+    }
+
+    async fn fetch(url: &str, settings: &HttpSettings) -> Result<Vec<u8>, FetchError> {
+        match fetch_with(&build_client(settings), settings, url, None).await? {
+            Fetched::Body(body, _) => Ok(body),
+            Fetched::NotModified => panic!("304 without validators"),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let (url, hits) = stand_in(vec![
+            Reply::Status(503),
+            Reply::Status(500),
+            Reply::Status(200),
+        ])
+        .await;
+
+        let body = fetch(&url, &test_settings(4)).await.unwrap();
+
+        assert_eq!(body, b"ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limit_and_timeouts() {
+        let (url, hits) = stand_in(vec![Reply::Hang, Reply::Status(429), Reply::Status(200)]).await;
+
+        let body = fetch(&url, &test_settings(4)).await.unwrap();
+
+        assert_eq!(body, b"ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_not_found() {
+        let (url, hits) = stand_in(vec![Reply::Status(404)]).await;
+
+        let err = fetch(&url, &test_settings(4)).await.unwrap_err();
+
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_configured_retries() {
+        let (url, hits) = stand_in(vec![Reply::Status(502)]).await;
+
+        let err = fetch(&url, &test_settings(2)).await.unwrap_err();
+
+        assert_eq!(err.status(), Some(StatusCode::BAD_GATEWAY));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    fn part_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("neko-http-{}-{}.part", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_download_with_range() {
+        let (url, hits) = stand_in(vec![Reply::Cut(10), Reply::Ranged]).await;
+        let part = part_path("resume");
+        let settings = test_settings(4);
+
+        stream_with(&build_client(&settings), &settings, &url, &part)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&part).unwrap(), BODY);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[tokio::test]
+    async fn restarts_when_server_ignores_range() {
+        let (url, _) = stand_in(vec![Reply::Status(200)]).await;
+        let part = part_path("ignored-range");
+        std::fs::write(&part, b"stale bytes").unwrap();
+        let settings = test_settings(4);
+
+        stream_with(&build_client(&settings), &settings, &url, &part)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&part).unwrap(), b"ok");
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uses_mirror_for_matching_urls() {
+        let (origin, origin_hits) = stand_in(vec![Reply::Status(500)]).await;
+        let (mirror, mirror_hits) = stand_in(vec![Reply::Status(200)]).await;
+        let settings = HttpSettings {
+            mirrors: vec![Mirror {
+                origin: origin.trim_end_matches("/file").to_owned(),
+                mirror: mirror.trim_end_matches("/file").to_owned(),
+            }],
+            ..test_settings(0)
+        };
+
+        let body = fetch(&origin, &settings).await.unwrap();
+
+        assert_eq!(body, b"ok");
+        assert_eq!(mirror_hits.load(Ordering::SeqCst), 1);
+        assert_eq!(origin_hits.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_origin_when_mirror_fails() {
+        let (origin, origin_hits) = stand_in(vec![Reply::Status(200)]).await;
+        let (mirror, mirror_hits) = stand_in(vec![Reply::Status(404)]).await;
+        let settings = HttpSettings {
+            mirrors: vec![Mirror {
+                origin: origin.trim_end_matches("/file").to_owned(),
+                mirror: mirror.trim_end_matches("/file").to_owned(),
+            }],
+            ..test_settings(0)
+        };
+
+        let body = fetch(&origin, &settings).await.unwrap();
+
+        assert_eq!(body, b"ok");
+        assert_eq!(mirror_hits.load(Ordering::SeqCst), 1);
+        assert_eq!(origin_hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn mirror_prefix_must_end_at_path_boundary() {
+        let settings = HttpSettings {
+            mirrors: parse_mirror("https://meta.fabricmc.net=http://127.0.0.1:8080/fabric")
+                .unwrap(),
+            ..HttpSettings::default()
+        };
+
+        assert_eq!(
+            mirrored(&settings, "https://meta.fabricmc.net/v2/versions").as_deref(),
+            Some("http://127.0.0.1:8080/fabric/v2/versions")
+        );
+        assert_eq!(mirrored(&settings, "https://meta.fabricmc.network/x"), None);
+    }
+
+    #[tokio::test]
+    async fn revalidates_cached_response_with_etag() {
+        let (url, hits) = stand_in(vec![Reply::Etag]).await;
+        let settings = test_settings(0);
+        let client = build_client(&settings);
+
+        let validators = match fetch_with(&client, &settings, &url, None).await.unwrap() {
+            Fetched::Body(body, validators) => {
+                assert_eq!(body, b"ok");
+                validators
+            }
+            Fetched::NotModified => panic!("first request has no validators"),
+        };
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+
+        let revalidated = fetch_with(&client, &settings, &url, Some(&validators))
+            .await
+            .unwrap();
+
+        assert!(matches!(revalidated, Fetched::NotModified));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,533 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use futures::future::LocalBoxFuture;
+use tokio::fs;
+
+use crate::{
+    cache::cache,
+    download::{download_all, hash_file, is_present, part_path, restore_from_cache, DownloadTask},
+    http::{get_bytes, get_index, resolve},
+    java_providers::{runtime_provider, InstalledRuntime, RuntimeProvider},
+    lock::{pinned_file, record_file, Resolved},
+    mojang::get_client_manifest,
+    types::{
+        DownloadInfo, FileEntry, JavaFileObject, JavaImage, JavaObject, JavaProvider, JavaRuntime,
+        OsType, Platform, SelectedJavaManifest,
+    },
+    utils::sha1_hex,
+};
+
+pub const JAVA_RUNTIMES_URL: &str = "https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json";
+
+/// Платформы по умолчанию, если `--java-platform` не указан
+pub const DEFAULT_PLATFORMS: [Platform; 5] = [
+    Platform::WindowsX64,
+    Platform::WindowsArm64,
+    Platform::MacOs,
+    Platform::MacOsArm64,
+    Platform::Linux,
+];
+
+/// Файлы рантайма и его симлинки, пути относительно папки рантайма
+pub type RuntimeFiles = (BTreeMap<String, JavaFileObject>, BTreeMap<String, String>);
+
+static JAVA_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+static PROVIDER: OnceLock<Box<dyn RuntimeProvider>> = OnceLock::new();
+
+/// Скачанные за запуск рантаймы для секции `java` в manifest.json
+static RUNTIMES: Mutex<BTreeMap<Platform, JavaObject>> = Mutex::new(BTreeMap::new());
+
+/// Java из `--java`, проверяется первой
+pub fn set_java(path: PathBuf) {
+    JAVA_OVERRIDE.set(path).expect("Java already set");
+}
+
+/// `--java-provider` и `--java-image`
+pub fn set_provider(provider: JavaProvider, image: JavaImage) {
+    if PROVIDER.set(runtime_provider(provider, image)).is_err() {
+        panic!("Java provider already set");
+    }
+}
+
+fn provider() -> &'static dyn RuntimeProvider {
+    PROVIDER.get_or_init(|| Box::new(MojangRuntimes)).as_ref()
+}
+
+/// Java для процессоров установщика: `--java`, `JAVA_HOME`, рантайм, скачанный
+/// через `--java-platform` для этой платформы, или рантайм в кеше (при необходимости
+/// скачивается туда). Мажорная версия сверяется с `javaVersion` из манифеста версии.
+pub async fn resolve_java(
+    mc_version: &str,
+    root: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let (component, major) = required_java(mc_version).await?;
+
+    if let Some(java) = JAVA_OVERRIDE.get() {
+        return match java_major(java) {
+            Some(found) if found == major => Ok(java.clone()),
+            found => Err(format!(
+                "--java {:?} is Java {}, but {} needs Java {}",
+                java,
+                describe(found),
+                mc_version,
+                major
+            )
+            .into()),
+        };
+    }
+
+    if let Some(home) = env::var_os("JAVA_HOME") {
+        let java = Path::new(&home).join("bin").join(java_executable());
+        match java_major(&java) {
+            Some(found) if found == major => {
+                println!("Using Java {} from JAVA_HOME: {:?}", major, java);
+                return Ok(java);
+            }
+            found => println!(
+                "JAVA_HOME is Java {}, {} needs Java {}, looking for a downloaded runtime",
+                describe(found),
+                mc_version,
+                major
+            ),
+        }
+    }
+
+    let platform =
+        host_platform().ok_or("No Java runtime for this platform, pass --java or set JAVA_HOME")?;
+    let name = runtime_name(&component, major);
+
+    // Рантайм, запрошенный через `--java-platform` для этой же платформы, уже есть в манифесте
+    let requested = RUNTIMES
+        .lock()
+        .unwrap()
+        .get(&platform)
+        .is_some_and(|runtime| runtime.component == name);
+
+    if requested {
+        let java = runtime_java(&root.join(runtime_path(platform, &name)), platform);
+        if java_major(&java) == Some(major) {
+            println!("Using downloaded Java {}: {:?}", major, java);
+            return Ok(java);
+        }
+    }
+
+    // Остальное нужно только для сборки: в папке сервера, которая уходит клиентам, ему не место
+    let dir = build_java_dir().join(runtime_path(platform, &name));
+    let java = runtime_java(&dir, platform);
+
+    if java_major(&java) == Some(major) {
+        println!("Using cached Java {}: {:?}", major, java);
+        return Ok(java);
+    }
+
+    println!(
+        "Downloading Java {} ({}) for {}",
+        major,
+        name,
+        platform.name()
+    );
+
+    install_runtime(platform, &component, major, &dir)
+        .await?
+        .ok_or_else(|| format!("No {} for {}", name, platform.name()))?;
+
+    match java_major(&java) {
+        Some(found) if found == major => Ok(java),
+        found => Err(format!(
+            "Downloaded runtime {:?} reports Java {}, expected {}",
+            java,
+            describe(found),
+            major
+        )
+        .into()),
+    }
+}
+
+/// Куда ставится Java для процессоров: в общий кеш, а с `--no-cache` - во временную папку
+fn build_java_dir() -> PathBuf {
+    match cache() {
+        Some(cache) => cache.root.clone(),
+        None => env::temp_dir().join("neko-manifest"),
+    }
+}
+
+/// Компонент рантайма Mojang и мажорная версия Java из манифеста версии
+pub async fn required_java(mc_version: &str) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    // До 1.17 javaVersion в манифесте нет, такие версии идут на Java 8
+    Ok(match get_client_manifest(mc_version).await?.javaVersion {
+        Some(java_version) => (java_version.component, java_version.majorVersion),
+        None => ("jre-legacy".to_owned(), 8),
+    })
+}
+
+/// Рантайм из `javaVersion` версии в `<server>/java/<platform>/<name>`
+/// для каждой выбранной платформы, где он есть
+pub async fn download_java(
+    server_name: &str,
+    mc_version: &str,
+    platforms: &[Platform],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (component, major) = required_java(mc_version).await?;
+    let name = runtime_name(&component, major);
+
+    println!("Java {} ({})", major, name);
+
+    for &platform in platforms {
+        let path = runtime_path(platform, &name);
+
+        let installed = install_runtime(
+            platform,
+            &component,
+            major,
+            &Path::new(server_name).join(&path),
+        )
+        .await
+        .map_err(|e| format!("{}: {}", platform.name(), e))?;
+
+        let Some((version, (files, links))) = installed else {
+            println!(
+                "[SKIPPED] {} is not available for {}",
+                name,
+                platform.name()
+            );
+            continue;
+        };
+
+        println!("Successfully downloaded for {}", platform.name());
+
+        RUNTIMES.lock().unwrap().insert(
+            platform,
+            JavaObject {
+                path,
+                component: name.clone(),
+                version,
+                files,
+                links,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Ставит рантайм выбранного провайдера в `dir` и возвращает его версию и файлы.
+/// `None`, если для платформы сборки нет
+async fn install_runtime(
+    platform: Platform,
+    component: &str,
+    major: u64,
+    dir: &Path,
+) -> Result<InstalledRuntime, Box<dyn std::error::Error>> {
+    provider().install(platform, component, major, dir).await
+}
+
+/// java-runtime из лаунчера Mojang
+pub struct MojangRuntimes;
+
+impl RuntimeProvider for MojangRuntimes {
+    fn name(&self, component: &str, _major: u64) -> String {
+        component.to_owned()
+    }
+
+    fn install<'a>(
+        &'a self,
+        platform: Platform,
+        component: &'a str,
+        _major: u64,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<InstalledRuntime, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let key = format!("{}#{}/{}", JAVA_RUNTIMES_URL, platform.name(), component);
+            let resolved = resolve(&key, || async {
+                let runtimes: JavaRuntime = get_index(JAVA_RUNTIMES_URL).await?;
+
+                Ok(runtimes
+                    .platform(platform)
+                    .and_then(|versions| versions.component(component))
+                    .map(|details| Resolved {
+                        url: details.manifest.url.clone(),
+                        sha1: Some(details.manifest.sha1.clone()),
+                        sha256: None,
+                        size: Some(details.manifest.size as u64),
+                        version: Some(details.version.name.clone()),
+                    }))
+            })
+            .await?;
+
+            let Some(resolved) = resolved else {
+                return Ok(None);
+            };
+
+            let body = get_bytes(&resolved.url).await?;
+            if resolved.sha1.as_deref() != Some(sha1_hex(&body).as_str()) {
+                return Err(format!("{}: sha1 does not match {}", resolved.url, key).into());
+            }
+
+            let manifest: SelectedJavaManifest =
+                serde_json::from_slice(&body).map_err(|e| format!("{}: {}", resolved.url, e))?;
+            let files = java_downloader(manifest, dir).await?;
+
+            Ok(Some((resolved.version.unwrap_or_default(), files)))
+        })
+    }
+}
+
+/// Рантаймы, скачанные `download_java`
+pub fn runtimes() -> BTreeMap<Platform, JavaObject> {
+    std::mem::take(&mut RUNTIMES.lock().unwrap())
+}
+
+/// Платформа, на которой запущен CLI
+fn host_platform() -> Option<Platform> {
+    match (env::consts::OS, env::consts::ARCH) {
+        ("linux", "x86_64") => Some(Platform::Linux),
+        ("linux", "x86") => Some(Platform::LinuxI386),
+        ("linux", "aarch64") => Some(Platform::LinuxArm64),
+        ("macos", "x86_64") => Some(Platform::MacOs),
+        ("macos", "aarch64") => Some(Platform::MacOsArm64),
+        ("windows", "x86_64") => Some(Platform::WindowsX64),
+        ("windows", "x86") => Some(Platform::WindowsX86),
+        ("windows", "aarch64") => Some(Platform::WindowsArm64),
+        _ => None,
+    }
+}
+
+/// Имя рантайма в папке и manifest.json: компонент Mojang или `adoptium-17-jre`
+fn runtime_name(component: &str, major: u64) -> String {
+    provider().name(component, major)
+}
+
+/// Папка рантайма относительно папки сервера
+fn runtime_path(platform: Platform, name: &str) -> String {
+    format!("java/{}/{}", platform.name(), name)
+}
+
+fn runtime_java(dir: &Path, platform: Platform) -> PathBuf {
+    // На macOS рантайм упакован в bundle
+    let home = if platform.os() == OsType::MacOs {
+        dir.join("jre.bundle").join("Contents").join("Home")
+    } else {
+        dir.to_path_buf()
+    };
+
+    home.join("bin").join(java_executable())
+}
+
+fn java_executable() -> &'static str {
+    if cfg!(windows) {
+        "java.exe"
+    } else {
+        "java"
+    }
+}
+
+/// Мажорная версия по `java -version`: `"17.0.8"` -> 17, `"1.8.0_392"` -> 8
+fn java_major(java: &Path) -> Option<u64> {
+    let output = Command::new(java).arg("-version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stderr);
+
+    let version = text.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-', '+']);
+
+    match parts.next()? {
+        "1" => parts.next()?.parse().ok(),
+        major => major.parse().ok(),
+    }
+}
+
+fn describe(major: Option<u64>) -> String {
+    major.map_or_else(|| "unknown".to_owned(), |major| major.to_string())
+}
+
+/// Раскладывает рантайм Mojang в `dir` как есть: папки, файлы с флагом executable
+/// и симлинки. Файлы качаются в LZMA, если он есть, и проверяются по SHA-1 и размеру
+/// распакованного файла. Возвращает файлы и симлинки для manifest.json.
+pub async fn java_downloader(
+    selected_java_manifest: SelectedJavaManifest,
+    dir: &Path,
+) -> Result<RuntimeFiles, Box<dyn std::error::Error>> {
+    let mut files = BTreeMap::new();
+    let mut tasks = vec![];
+    // Файлы, которые приедут в LZMA: распакуются после загрузки
+    let mut packed = vec![];
+    let mut executables = vec![];
+    let mut links = BTreeMap::new();
+
+    for (path, entry) in selected_java_manifest.files {
+        match entry {
+            FileEntry::Directory => {
+                fs::create_dir_all(dir.join(&path)).await?;
+            }
+            FileEntry::File {
+                downloads,
+                executable,
+            } => {
+                let task = DownloadTask {
+                    name: path.clone(),
+                    url: downloads.raw.url.clone(),
+                    path: dir.join(&path),
+                    sha1: Some(downloads.raw.sha1.clone()),
+                    size: Some(downloads.raw.size),
+                };
+
+                if executable {
+                    executables.push(task.path.clone());
+                }
+
+                files.insert(
+                    path.clone(),
+                    JavaFileObject {
+                        sha1: downloads.raw.sha1.clone(),
+                        size: downloads.raw.size,
+                        executable,
+                    },
+                );
+
+                match downloads.lzma {
+                    Some(lzma) if prefer_lzma(&task, &lzma).await => {
+                        tasks.push(DownloadTask {
+                            name: path,
+                            url: lzma.url,
+                            path: lzma_path(&task.path),
+                            sha1: Some(lzma.sha1),
+                            size: Some(lzma.size),
+                        });
+                        packed.push((task, downloads.raw));
+                    }
+                    _ => tasks.push(task),
+                }
+            }
+            FileEntry::Link { target } => {
+                links.insert(path, target);
+            }
+            FileEntry::Ignored => {}
+        }
+    }
+
+    download_all(tasks).await?;
+
+    for (task, raw) in packed {
+        unpack_lzma(&task, &raw).await?;
+    }
+
+    for path in executables {
+        make_executable(&path)?;
+    }
+
+    create_links(dir, &links).await?;
+
+    Ok((files, links))
+}
+
+/// LZMA качаем, только если готового файла нет ни на диске, ни в кеше.
+/// С `--locked` - только если LZMA закреплён в neko.lock.
+async fn prefer_lzma(task: &DownloadTask, lzma: &DownloadInfo) -> bool {
+    pinned_file(&lzma.url).is_ok() && !is_present(task).await && !restore_from_cache(task).await
+}
+
+fn lzma_path(path: &Path) -> PathBuf {
+    let mut packed = path.as_os_str().to_owned();
+    packed.push(".lzma");
+    PathBuf::from(packed)
+}
+
+/// Распаковывает `<файл>.lzma` рядом и проверяет результат по хешу из `raw`.
+/// Распакованный файл кладётся в кеш и в neko.lock, как если бы его скачали напрямую.
+async fn unpack_lzma(
+    task: &DownloadTask,
+    raw: &DownloadInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let packed = lzma_path(&task.path);
+    let part = part_path(&task.path);
+
+    let (src, dest) = (packed.clone(), part.clone());
+    tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let mut input = BufReader::new(File::open(&src).map_err(|e| e.to_string())?);
+        let mut output = BufWriter::new(File::create(&dest).map_err(|e| e.to_string())?);
+        lzma_rs::lzma_decompress(&mut input, &mut output).map_err(|e| e.to_string())
+    })
+    .await?
+    .map_err(|e| format!("{}: failed to unpack LZMA: {}", task.name, e))?;
+
+    fs::remove_file(&packed).await?;
+
+    let (sha1, size) = hash_file(&part).await?;
+    if !sha1.eq_ignore_ascii_case(&raw.sha1) || size != raw.size {
+        let _ = fs::remove_file(&part).await;
+        return Err(format!(
+            "{}: unpacked file is {} ({} bytes), expected {} ({} bytes)",
+            task.name, sha1, size, raw.sha1, raw.size
+        )
+        .into());
+    }
+
+    fs::rename(&part, &task.path).await?;
+
+    if let Some(cache) = cache() {
+        if let Err(e) = cache.store(&raw.sha1, &task.path).await {
+            eprintln!("[CACHE] Failed to store {}: {}", task.name, e);
+        }
+    }
+
+    record_file(&raw.url, &raw.sha1, raw.size);
+
+    Ok(())
+}
+
+/// Флаг executable из манифеста. Права только добавляются: файл может быть
+/// хардлинком на объект кеша, который используется где-то ещё.
+#[cfg(unix)]
+pub fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+pub fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+pub async fn create_links(
+    dir: &Path,
+    links: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (path, target) in links {
+        let link = dir.join(path);
+
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Старый симлинк или файл на его месте
+        if fs::symlink_metadata(&link).await.is_ok() {
+            fs::remove_file(&link).await?;
+        }
+
+        fs::symlink(target, &link).await?;
+    }
+
+    Ok(())
+}
+
+/// Без прав администратора Windows симлинки не создаёт - они есть в manifest.json,
+/// лаунчер восстановит их на клиенте
+#[cfg(not(unix))]
+pub async fn create_links(
+    _dir: &Path,
+    _links: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
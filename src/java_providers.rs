@@ -0,0 +1,615 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use futures::future::LocalBoxFuture;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::{
+    cache::cache,
+    download::{download_verified, DownloadError, DownloadTask},
+    http::{get_index, get_index_bytes, resolve, FetchError},
+    java::{create_links, make_executable, MojangRuntimes, RuntimeFiles},
+    lock::{pinned_file, record_file, Resolved, LOCK_FILE},
+    types::{
+        AdoptiumRelease, JavaFileObject, JavaImage, JavaProvider, OsType, Platform, ZuluPackage,
+        ZuluPackageDetails,
+    },
+    utils::hex,
+};
+
+/// Версия поставленного рантайма и его файлы. `None`, если для платформы сборки нет
+pub type InstalledRuntime = Option<(String, RuntimeFiles)>;
+
+/// Источник рантаймов Java (`--java-provider`). Новый провайдер - ещё одна реализация
+/// и строка в `runtime_provider`
+pub trait RuntimeProvider: Send + Sync {
+    /// Имя папки рантайма и компонента в manifest.json
+    fn name(&self, component: &str, major: u64) -> String;
+
+    /// Ставит рантайм с `component` (имя у Mojang) или `major` (у остальных) в `dir`
+    fn install<'a>(
+        &'a self,
+        platform: Platform,
+        component: &'a str,
+        major: u64,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<InstalledRuntime, Box<dyn std::error::Error>>>;
+}
+
+pub fn runtime_provider(provider: JavaProvider, image: JavaImage) -> Box<dyn RuntimeProvider> {
+    match provider {
+        JavaProvider::Mojang => Box::new(MojangRuntimes),
+        JavaProvider::Adoptium => Box::new(Adoptium { image }),
+        JavaProvider::Zulu => Box::new(Zulu { image }),
+    }
+}
+
+/// Eclipse Temurin из API Adoptium
+struct Adoptium {
+    image: JavaImage,
+}
+
+impl RuntimeProvider for Adoptium {
+    fn name(&self, _component: &str, major: u64) -> String {
+        format!("adoptium-{}-{}", major, self.image.name())
+    }
+
+    fn install<'a>(
+        &'a self,
+        platform: Platform,
+        _component: &'a str,
+        major: u64,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<InstalledRuntime, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let archive = adoptium_archive(platform, major, self.image).await?;
+            install_latest(archive, platform, dir).await
+        })
+    }
+}
+
+/// Azul Zulu из Azul Metadata API
+struct Zulu {
+    image: JavaImage,
+}
+
+impl RuntimeProvider for Zulu {
+    fn name(&self, _component: &str, major: u64) -> String {
+        format!("zulu-{}-{}", major, self.image.name())
+    }
+
+    fn install<'a>(
+        &'a self,
+        platform: Platform,
+        _component: &'a str,
+        major: u64,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<InstalledRuntime, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let archive = zulu_archive(platform, major, self.image).await?;
+            install_latest(archive, platform, dir).await
+        })
+    }
+}
+
+async fn install_latest(
+    archive: Option<RuntimeArchive>,
+    platform: Platform,
+    dir: &Path,
+) -> Result<InstalledRuntime, Box<dyn std::error::Error>> {
+    let Some(archive) = archive else {
+        return Ok(None);
+    };
+
+    let version = archive.version.clone();
+    let files = install_archive(archive, platform, dir).await?;
+
+    Ok(Some((version, files)))
+}
+
+/// Архив с рантаймом от Adoptium или Azul
+struct RuntimeArchive {
+    name: String,
+    url: String,
+    size: u64,
+    sha256: String,
+    version: String,
+}
+
+impl RuntimeArchive {
+    /// Архив, выбранный по "latest" сейчас или записанный в neko.lock
+    fn from_resolved(resolved: Resolved) -> Result<RuntimeArchive, Box<dyn std::error::Error>> {
+        let incomplete = || format!("{}: incomplete entry in {}", resolved.url, LOCK_FILE);
+
+        Ok(RuntimeArchive {
+            name: resolved
+                .url
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())
+                .ok_or_else(incomplete)?
+                .to_owned(),
+            size: resolved.size.ok_or_else(incomplete)?,
+            sha256: resolved.sha256.clone().ok_or_else(incomplete)?,
+            version: resolved.version.clone().ok_or_else(incomplete)?,
+            url: resolved.url,
+        })
+    }
+}
+
+/// Последняя сборка Temurin. `None`, если для платформы её нет
+async fn adoptium_archive(
+    platform: Platform,
+    major: u64,
+    image: JavaImage,
+) -> Result<Option<RuntimeArchive>, Box<dyn std::error::Error>> {
+    let os = match platform.os() {
+        OsType::Windows => "windows",
+        OsType::MacOs => "mac",
+        OsType::Linux => "linux",
+    };
+    let arch = match platform.arch() {
+        "x86_64" => "x64",
+        "x86" => "x32",
+        _ => "aarch64",
+    };
+
+    let url = format!(
+        "https://api.adoptium.net/v3/assets/latest/{}/hotspot?architecture={}&image_type={}&os={}&vendor=eclipse",
+        major,
+        arch,
+        image.name(),
+        os
+    );
+
+    let resolved = resolve(&url, || async {
+        let releases: Vec<AdoptiumRelease> = match get_index_bytes(&url).await {
+            Ok(body) => serde_json::from_slice(&body).map_err(|e| format!("{}: {}", url, e))?,
+            // На запрос без сборок API отвечает 404
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(releases.into_iter().next().map(|release| Resolved {
+            url: release.binary.package.link,
+            sha1: None,
+            sha256: Some(release.binary.package.checksum),
+            size: Some(release.binary.package.size),
+            version: Some(release.version.openjdk_version),
+        }))
+    })
+    .await?;
+
+    resolved.map(RuntimeArchive::from_resolved).transpose()
+}
+
+/// Последняя сборка Zulu. `None`, если для платформы её нет
+async fn zulu_archive(
+    platform: Platform,
+    major: u64,
+    image: JavaImage,
+) -> Result<Option<RuntimeArchive>, Box<dyn std::error::Error>> {
+    let (os, archive_type) = match platform.os() {
+        OsType::Windows => ("windows", "zip"),
+        OsType::MacOs => ("macos", "tar.gz"),
+        // Без `_glibc` в выдачу попадают сборки под musl
+        OsType::Linux => ("linux_glibc", "tar.gz"),
+    };
+    let arch = match platform.arch() {
+        "x86_64" => "x64",
+        "x86" => "i686",
+        _ => "aarch64",
+    };
+
+    let url = format!(
+        "https://api.azul.com/metadata/v1/zulu/packages/?java_version={}&os={}&arch={}&archive_type={}&java_package_type={}&javafx_bundled=false&crac_supported=false&latest=true&release_status=ga&availability_types=CA&page_size=1",
+        major,
+        os,
+        arch,
+        archive_type,
+        image.name()
+    );
+
+    let resolved = resolve(&url, || async {
+        let packages: Vec<ZuluPackage> = get_index(&url).await?;
+
+        let Some(package) = packages.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let details: ZuluPackageDetails = get_index(&format!(
+            "https://api.azul.com/metadata/v1/zulu/packages/{}",
+            package.package_uuid
+        ))
+        .await?;
+
+        Ok(Some(Resolved {
+            url: package.download_url,
+            sha1: None,
+            sha256: Some(details.sha256_hash),
+            size: Some(details.size),
+            version: Some(
+                package
+                    .java_version
+                    .iter()
+                    .map(|part| part.to_string())
+                    .collect::<Vec<_>>()
+                    .join("."),
+            ),
+        }))
+    })
+    .await?;
+
+    resolved.map(RuntimeArchive::from_resolved).transpose()
+}
+
+/// Что распаковано в папку рантайма: лежит рядом с ней в `<имя>.json`
+#[derive(Serialize, Deserialize)]
+struct Installed {
+    url: String,
+    sha256: String,
+    /// SHA-1 архива для neko.lock, чтобы не пересчитывать его при каждом запуске
+    sha1: String,
+    files: BTreeMap<String, JavaFileObject>,
+    links: BTreeMap<String, String>,
+}
+
+/// Качает архив, сверяет SHA-256 и распаковывает его в `dir` в той же раскладке,
+/// что и рантаймы Mojang. Уже распакованный и не тронутый с тех пор архив не качается вовсе,
+/// а сам архив хранится в кеше по SHA-256
+async fn install_archive(
+    archive: RuntimeArchive,
+    platform: Platform,
+    dir: &Path,
+) -> Result<RuntimeFiles, Box<dyn std::error::Error>> {
+    let record = installed_path(dir);
+
+    let installed = tokio::fs::read(&record)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Installed>(&bytes).ok())
+        .filter(|installed| {
+            installed.url == archive.url && installed.sha256.eq_ignore_ascii_case(&archive.sha256)
+        });
+
+    if let Some(installed) = installed {
+        let checked = dir.to_path_buf();
+        let (installed, intact) = tokio::task::spawn_blocking(move || {
+            let intact = is_intact(&checked, &installed);
+            (installed, intact)
+        })
+        .await?;
+
+        if intact {
+            println!("{}: already unpacked", archive.name);
+            record_file(&archive.url, &installed.sha1, archive.size);
+            return Ok((installed.files, installed.links));
+        }
+    }
+
+    let path = env::temp_dir().join(&archive.name);
+
+    let cached = match cache() {
+        Some(cache) => cache.place(&archive.sha256, &path).await.unwrap_or(false),
+        None => false,
+    };
+
+    // Мимо `download_all`: тот положил бы архив в кеш ещё раз, под SHA-1
+    if !cached {
+        let pinned =
+            pinned_file(&archive.url).map_err(|e| FetchError::from_lock(&archive.url, e))?;
+        let task = DownloadTask {
+            name: archive.name.clone(),
+            url: archive.url.clone(),
+            path: path.clone(),
+            sha1: pinned.map(|pinned| pinned.sha1.clone()),
+            size: Some(archive.size),
+        };
+
+        download_verified(&task)
+            .await
+            .map_err(|e| format!("{}: {}", task.name, e))?;
+        println!("{}: {} -> {:?}", task.name, task.url, task.path);
+    }
+
+    let (sha256, sha1) = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || hash_archive(&path)).await??
+    };
+
+    if !sha256.eq_ignore_ascii_case(&archive.sha256) {
+        if let (true, Some(cache)) = (cached, cache()) {
+            cache.evict(&archive.sha256).await;
+        }
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(format!(
+            "{}: expected sha256 {}, got {}",
+            archive.name, archive.sha256, sha256
+        )
+        .into());
+    }
+
+    if let (false, Some(cache)) = (cached, cache()) {
+        if let Err(e) = cache.store(&sha256, &path).await {
+            eprintln!("[CACHE] Failed to store {}: {}", archive.name, e);
+        }
+    }
+
+    record_file(&archive.url, &sha1, archive.size);
+
+    let dest = dir.to_path_buf();
+    let name = archive.name.clone();
+    let mac = platform.os() == OsType::MacOs;
+    let _ = tokio::fs::remove_file(&record).await;
+
+    let unpacked = tokio::task::spawn_blocking(move || -> Result<RuntimeFiles, DownloadError> {
+        // Старая версия рантайма не должна оставить лишних файлов
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+
+        let result = unpack(&path, &name, &dest, mac);
+        let _ = fs::remove_file(&path);
+        result
+    })
+    .await?;
+
+    let (files, links) = unpacked.map_err(|e| e as Box<dyn std::error::Error>)?;
+
+    create_links(dir, &links).await?;
+
+    let installed = Installed {
+        url: archive.url,
+        sha256: archive.sha256,
+        sha1,
+        files,
+        links,
+    };
+    tokio::fs::write(&record, serde_json::to_vec_pretty(&installed)?).await?;
+
+    Ok((installed.files, installed.links))
+}
+
+/// `java/<platform>/<name>` -> `java/<platform>/<name>.json`
+fn installed_path(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    dir.with_file_name(name)
+}
+
+/// Все файлы на месте с прежними хешами, симлинки указывают туда же
+fn is_intact(dir: &Path, installed: &Installed) -> bool {
+    let files_intact = installed.files.iter().all(|(path, file)| {
+        let path = dir.join(path);
+        fs::metadata(&path).is_ok_and(|meta| meta.len() == file.size)
+            && sha1_file(&path).is_ok_and(|sha1| sha1 == file.sha1)
+    });
+
+    // Вне unix симлинки не создаются
+    let links_intact = !cfg!(unix)
+        || installed.links.iter().all(|(path, target)| {
+            fs::read_link(dir.join(path)).is_ok_and(|link| link == Path::new(target))
+        });
+
+    files_intact && links_intact
+}
+
+fn sha1_file(path: &Path) -> std::io::Result<String> {
+    let mut sha1 = Sha1::new();
+    std::io::copy(&mut File::open(path)?, &mut sha1)?;
+    Ok(hex(&sha1.finalize()))
+}
+
+/// SHA-256 и SHA-1 архива за одно чтение
+fn hash_archive(path: &Path) -> std::io::Result<(String, String)> {
+    let mut file = File::open(path)?;
+    let mut sha256 = Sha256::new();
+    let mut sha1 = Sha1::new();
+    let mut buffer = vec![0; 1 << 16];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+        sha1.update(&buffer[..read]);
+    }
+
+    Ok((hex(&sha256.finalize()), hex(&sha1.finalize())))
+}
+
+fn unpack(
+    archive: &Path,
+    name: &str,
+    dir: &Path,
+    mac: bool,
+) -> Result<RuntimeFiles, DownloadError> {
+    let mut unpacker = Unpacker {
+        dir,
+        mac,
+        files: BTreeMap::new(),
+        links: BTreeMap::new(),
+    };
+
+    if name.ends_with(".zip") {
+        let mut zip = ZipArchive::new(File::open(archive)?)?;
+
+        for index in 0..zip.len() {
+            let mut entry = zip.by_index(index)?;
+            let Some(path) = unpacker.runtime_path(entry.name()) else {
+                continue;
+            };
+
+            if entry.is_dir() {
+                fs::create_dir_all(dir.join(&path))?;
+            } else if entry.is_symlink() {
+                let mut target = String::new();
+                entry.read_to_string(&mut target)?;
+                unpacker.links.insert(path, target);
+            } else {
+                let executable = entry.unix_mode().is_some_and(|mode| mode & 0o111 != 0);
+                unpacker.file(path, &mut entry, executable)?;
+            }
+        }
+    } else if name.ends_with(".tar.gz") {
+        let mut tar = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let raw = entry.path()?.to_string_lossy().replace('\\', "/");
+            let Some(path) = unpacker.runtime_path(&raw) else {
+                continue;
+            };
+
+            let kind = entry.header().entry_type();
+
+            if kind.is_dir() {
+                fs::create_dir_all(dir.join(&path))?;
+            } else if kind.is_symlink() || kind.is_hard_link() {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| format!("{}: link without target", raw))?
+                    .to_string_lossy()
+                    .replace('\\', "/");
+
+                if kind.is_symlink() {
+                    unpacker.links.insert(path, target);
+                } else {
+                    unpacker.hard_link(path, &target)?;
+                }
+            } else if kind.is_file() {
+                let executable = entry.header().mode()? & 0o111 != 0;
+                unpacker.file(path, &mut entry, executable)?;
+            }
+        }
+    } else {
+        return Err(format!("{}: unsupported archive", name).into());
+    }
+
+    Ok((unpacker.files, unpacker.links))
+}
+
+struct Unpacker<'a> {
+    dir: &'a Path,
+    mac: bool,
+    files: BTreeMap<String, JavaFileObject>,
+    links: BTreeMap<String, String>,
+}
+
+impl Unpacker<'_> {
+    /// Путь внутри рантайма: без корневой папки архива, а на macOS - внутри `jre.bundle`,
+    /// как у Mojang. `None` для всего, что в рантайм не входит
+    fn runtime_path(&self, raw: &str) -> Option<String> {
+        let raw = raw.trim_start_matches("./").trim_end_matches('/');
+        let (_, path) = raw.split_once('/')?;
+
+        if path.split('/').any(|part| part == ".." || part.is_empty()) {
+            return None;
+        }
+
+        if !self.mac {
+            return Some(path.to_owned());
+        }
+
+        // Zulu кладёт bundle в `zulu-17.jre/`, Temurin - прямо в корень архива.
+        // Симлинки рядом с bundle (`bin -> zulu-17.jre/Contents/Home/bin`) не нужны
+        let inner = match path.split_once('/') {
+            Some((bundle, inner)) if bundle.ends_with(".jre") || bundle.ends_with(".jdk") => inner,
+            _ => path,
+        };
+
+        (inner == "Contents" || inner.starts_with("Contents/"))
+            .then(|| format!("jre.bundle/{}", inner))
+    }
+
+    fn file(
+        &mut self,
+        path: String,
+        reader: &mut impl Read,
+        executable: bool,
+    ) -> Result<(), DownloadError> {
+        let dest = self.dir.join(&path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // `lib/modules` весит больше 100 МБ, поэтому пишем потоком
+        let mut writer = HashingWriter {
+            inner: BufWriter::new(File::create(&dest)?),
+            sha1: Sha1::new(),
+            size: 0,
+        };
+        std::io::copy(reader, &mut writer)?;
+        writer.flush()?;
+
+        if executable {
+            make_executable(&dest)?;
+        }
+
+        self.files.insert(
+            path,
+            JavaFileObject {
+                sha1: hex(&writer.sha1.finalize()),
+                size: writer.size,
+                executable,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Хардлинк в tar указывает на уже распакованный файл архива - копируем его
+    fn hard_link(&mut self, path: String, target: &str) -> Result<(), DownloadError> {
+        let source = self
+            .runtime_path(target)
+            .filter(|source| self.files.contains_key(source))
+            .ok_or_else(|| format!("{}: hard link to unknown file {}", path, target))?;
+
+        let dest = self.dir.join(&path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(self.dir.join(&source), &dest)?;
+
+        let file = &self.files[&source];
+        let copied = JavaFileObject {
+            sha1: file.sha1.clone(),
+            size: file.size,
+            executable: file.executable,
+        };
+        self.files.insert(path, copied);
+
+        Ok(())
+    }
+}
+
+/// Пишет в файл и заодно считает SHA-1 и размер записанного
+struct HashingWriter<W> {
+    inner: W,
+    sha1: Sha1,
+    size: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.sha1.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{types::BuildArgs, utils::sha1_hex};
+
+pub const LOCK_FILE: &str = "neko.lock";
+const LOCK_VERSION: u32 = 2;
+
+/// Всё, что было скачано за запуск: метаданные (JSON версий, профили лоадеров,
+/// манифесты Java, `.sha1`) и файлы (библиотеки, клиент, установщики, Java, ассеты).
+/// Ключ - исходный URL, до подмены зеркалом.
+/// Индексы, которые меняются с каждым релизом, не закрепляются: вместо них в `resolved`
+/// лежит то, на что они указали.
+#[derive(Serialize, Deserialize, Default)]
+pub struct LockFile {
+    pub version: u32,
+    pub loader: String,
+    pub loader_version: String,
+    pub mc_version: String,
+    pub metadata: BTreeMap<String, Pinned>,
+    pub files: BTreeMap<String, Pinned>,
+    /// Запрос к индексу -> выбранный по нему файл. `null`, если индекс ничего не нашёл
+    pub resolved: BTreeMap<String, Option<Resolved>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pinned {
+    pub sha1: String,
+    pub size: u64,
+}
+
+/// Что выбрано по индексу: JSON версии из `version_manifest_v2.json`, манифест рантайма
+/// из `all.json` или архив из "latest" Adoptium и Zulu
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Resolved {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+#[derive(Clone, Default)]
+struct Recorded {
+    metadata: BTreeMap<String, Pinned>,
+    files: BTreeMap<String, Pinned>,
+    resolved: BTreeMap<String, Option<Resolved>>,
+}
+
+static RECORDED: Mutex<Recorded> = Mutex::new(Recorded {
+    metadata: BTreeMap::new(),
+    files: BTreeMap::new(),
+    resolved: BTreeMap::new(),
+});
+
+/// `--locked`: содержимое neko.lock, по которому идёт сборка
+static PINNED: OnceLock<LockFile> = OnceLock::new();
+
+/// Почему запрос не согласуется с neko.lock
+pub enum LockError {
+    /// URL нет в lock-файле
+    Missing,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Включает `--locked`: читает neko.lock и проверяет, что он от той же сборки
+pub fn load(server_name: &str, args: &BuildArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let path = Path::new(server_name).join(LOCK_FILE);
+    let lock: LockFile = serde_json::from_slice(
+        &std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?,
+    )?;
+
+    if lock.version != LOCK_VERSION {
+        return Err(format!("Unsupported {} version {}", LOCK_FILE, lock.version).into());
+    }
+
+    if (
+        lock.loader.as_str(),
+        lock.loader_version.as_str(),
+        lock.mc_version.as_str(),
+    ) != (
+        args.loader.as_str(),
+        args.loader_version.as_str(),
+        args.mc_version.as_str(),
+    ) {
+        return Err(format!(
+            "{} was written for {} {} {}, not {} {} {}",
+            LOCK_FILE,
+            lock.loader,
+            lock.loader_version,
+            lock.mc_version,
+            args.loader,
+            args.loader_version,
+            args.mc_version
+        )
+        .into());
+    }
+
+    PINNED.set(lock).map_err(|_| "Lock file already loaded")?;
+
+    Ok(())
+}
+
+pub fn is_locked() -> bool {
+    PINNED.get().is_some()
+}
+
+/// Закреплённый хеш ответа на URL. `Ok(None)` вне `--locked`
+pub fn pinned_metadata(url: &str) -> Result<Option<&'static Pinned>, LockError> {
+    match PINNED.get() {
+        Some(lock) => lock.metadata.get(url).map(Some).ok_or(LockError::Missing),
+        None => Ok(None),
+    }
+}
+
+/// Закреплённый хеш файла. `Ok(None)` вне `--locked`
+pub fn pinned_file(url: &str) -> Result<Option<&'static Pinned>, LockError> {
+    match PINNED.get() {
+        Some(lock) => lock.files.get(url).map(Some).ok_or(LockError::Missing),
+        None => Ok(None),
+    }
+}
+
+/// Во что разрешился запрос к индексу. `Ok(None)` вне `--locked`, тогда индекс нужно скачать
+pub fn pinned_resolved(key: &str) -> Result<Option<&'static Option<Resolved>>, LockError> {
+    match PINNED.get() {
+        Some(lock) => lock.resolved.get(key).map(Some).ok_or(LockError::Missing),
+        None => Ok(None),
+    }
+}
+
+pub fn record_resolved(key: &str, resolved: Option<Resolved>) {
+    RECORDED
+        .lock()
+        .unwrap()
+        .resolved
+        .insert(key.to_owned(), resolved);
+}
+
+/// Проверяет ответ по neko.lock и запоминает его для нового lock-файла. Возвращает SHA-1 ответа
+pub fn check_metadata(url: &str, body: &[u8]) -> Result<String, LockError> {
+    let sha1 = sha1_hex(body);
+
+    if let Some(pinned) = pinned_metadata(url)? {
+        if !pinned.sha1.eq_ignore_ascii_case(&sha1) {
+            return Err(LockError::Mismatch {
+                expected: pinned.sha1.clone(),
+                actual: sha1,
+            });
+        }
+    }
+
+    RECORDED.lock().unwrap().metadata.insert(
+        url.to_owned(),
+        Pinned {
+            sha1: sha1.clone(),
+            size: body.len() as u64,
+        },
+    );
+
+    Ok(sha1)
+}
+
+pub fn record_file(url: &str, sha1: &str, size: u64) {
+    RECORDED.lock().unwrap().files.insert(
+        url.to_owned(),
+        Pinned {
+            sha1: sha1.to_ascii_lowercase(),
+            size,
+        },
+    );
+}
+
+/// Пишет neko.lock рядом с manifest.json
+pub async fn write(server_name: &str, args: &BuildArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let Recorded {
+        metadata,
+        files,
+        resolved,
+    } = RECORDED.lock().unwrap().clone();
+
+    println!(
+        "{}: {} metadata response(s), {} file(s), {} index lookup(s)",
+        LOCK_FILE,
+        metadata.len(),
+        files.len(),
+        resolved.len()
+    );
+
+    let lock = LockFile {
+        version: LOCK_VERSION,
+        loader: args.loader.clone(),
+        loader_version: args.loader_version.clone(),
+        mc_version: args.mc_version.clone(),
+        metadata,
+        files,
+        resolved,
+    };
+
+    let mut json = serde_json::to_string_pretty(&lock)?;
+    json.push('\n');
+    tokio::fs::write(Path::new(server_name).join(LOCK_FILE), json).await?;
+
+    Ok(())
+}
@@ -1,17 +1,27 @@
+use cache::Cache;
 use clap::Parser;
-use futures::future::join_all;
-use std::{path::Path, sync::Arc};
-use tokio::{
-    fs::{self, create_dir},
-    io::AsyncWriteExt,
-    sync::Semaphore,
-};
-use types::{Cli, FileEntry, JavaRuntime, NekoManifest, SelectedJavaManifest};
+use download::set_parallelism;
+use http::{configure, parse_mirror, HttpSettings};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+use tokio::fs::create_dir;
+use types::{CacheCommand, Cli, Command, JavaImage, JavaProvider, NekoManifest};
 
 use forge::create_forge_manifest;
 
+mod assets;
+mod cache;
+mod download;
 mod forgeinstaller;
+mod http;
+mod java;
+mod java_providers;
+mod lock;
+mod manifest;
 mod mojang;
+mod processors;
+mod rules;
 mod types;
 mod utils;
 
@@ -19,12 +29,26 @@ mod utils;
 mod fabric;
 mod forge;
 mod neoforge;
+mod quilt;
+mod vanilla;
 
-use crate::{fabric::create_fabric_manifest, neoforge::create_neoforge_manifest};
+use crate::{
+    fabric::create_fabric_manifest, neoforge::create_neoforge_manifest,
+    quilt::create_quilt_manifest, vanilla::create_vanilla_manifest,
+};
 
 #[tokio::main]
 async fn main() {
-    let args = Cli::parse();
+    let cli = Cli::parse();
+    let cache_dir = cli.cache_dir.or_else(cache::default_dir);
+
+    if let Some(Command::Cache { action }) = cli.command {
+        run_cache_command(action, cache_dir);
+        return;
+    }
+
+    // Без подкоманды clap сам требует позиционные аргументы
+    let args = cli.build.expect("Build arguments are required");
     println!("Welcome to Neko Manifest CLI!");
     println!("Version: {}", env!("CARGO_PKG_VERSION"));
     println!("----------------------------");
@@ -32,16 +56,69 @@ async fn main() {
     println!("Selected loader: {}", args.loader);
     println!("Selected loader version: {}", args.loader_version);
     println!("Selected mc version: {}", args.mc_version);
-    match args.download_java {
-        Some(_) => {
-            println!("Downloading java for selected mc version");
-            if args.mc_version.parse::<f32>().unwrap() >= 1.16 {
-                download_java(21).await.ok();
-            } else {
-                download_java(17).await.ok();
+    set_parallelism(args.parallel);
+    let mut mirrors = Vec::new();
+    for spec in &args.mirrors {
+        match parse_mirror(spec) {
+            Ok(parsed) => mirrors.extend(parsed),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                exit(1);
             }
         }
-        None => {}
+    }
+    configure(HttpSettings {
+        retries: args.retries,
+        connect_timeout: Duration::from_secs(args.connect_timeout),
+        read_timeout: Duration::from_secs(args.read_timeout),
+        mirrors,
+        offline: args.offline,
+        ..HttpSettings::default()
+    });
+    cache::configure(match cache_dir {
+        Some(root) if !args.no_cache => {
+            println!("Download cache: {}", root.display());
+            Some(Cache {
+                root,
+                link: args.cache_link,
+            })
+        }
+        Some(_) => None,
+        None if args.offline => {
+            eprintln!("❌ Offline mode needs a cache directory, pass --cache-dir");
+            exit(1);
+        }
+        None => None,
+    });
+    if args.offline {
+        println!("Offline mode: using cached responses and files only");
+    }
+    if args.locked {
+        if let Err(e) = lock::load(&args.server_name, &args) {
+            eprintln!("❌ Failed to load {}: {}", lock::LOCK_FILE, e);
+            exit(1);
+        }
+        println!("Locked mode: building strictly from {}", lock::LOCK_FILE);
+    }
+    if let Some(path) = &args.java {
+        java::set_java(path.clone());
+    }
+    if args.java_image == JavaImage::Jdk && args.java_provider == JavaProvider::Mojang {
+        eprintln!("❌ Mojang has no JDK runtimes, pass --java-provider adoptium or zulu");
+        exit(1);
+    }
+    java::set_provider(args.java_provider, args.java_image);
+    if args.download_java.is_some() || !args.java_platforms.is_empty() {
+        println!("Downloading java for selected mc version");
+        let platforms = if args.java_platforms.is_empty() {
+            &java::DEFAULT_PLATFORMS[..]
+        } else {
+            &args.java_platforms[..]
+        };
+        if let Err(e) = java::download_java(&args.server_name, &args.mc_version, platforms).await {
+            eprintln!("❌ Failed to download java: {}", e);
+            exit(1);
+        }
     }
     println!("----------------------------");
 
@@ -52,208 +129,93 @@ async fn main() {
             .expect("Failed to create client folder");
     }
 
-    if args.loader == "fabric" {
+    let result = if args.loader == "fabric" {
         println!("Starting fabric manifest creation...");
         create_fabric_manifest(
-            args.server_name,
-            args.loader_version,
+            args.server_name.clone(),
+            args.loader_version.clone(),
+            args.mc_version.clone(),
+        )
+        .await
+    } else if args.loader == "quilt" {
+        println!("Starting quilt manifest creation...");
+        create_quilt_manifest(
+            args.server_name.clone(),
+            args.loader_version.clone(),
             args.mc_version.clone(),
         )
         .await
-        .unwrap();
     } else if args.loader == "forge" {
         println!("Starting forge manifest creation...");
         create_forge_manifest(
-            args.server_name,
-            args.loader_version,
+            args.server_name.clone(),
+            args.loader_version.clone(),
             args.mc_version.clone(),
         )
         .await
-        .unwrap();
     } else if args.loader == "neoforge" {
         println!("Starting neoforge manifest creation...");
         create_neoforge_manifest(
-            args.server_name,
-            args.loader_version,
+            args.server_name.clone(),
+            args.loader_version.clone(),
             args.mc_version.clone(),
         )
         .await
-        .unwrap();
+    } else if args.loader == "vanilla" {
+        println!("Starting vanilla manifest creation...");
+        create_vanilla_manifest(args.server_name.clone(), args.mc_version.clone()).await
     } else {
         println!("Supported loader not found");
-    }
-}
+        return;
+    };
 
-pub fn resolve_maven(file: &str) -> String {
-    if file == "minecraft.jar" {
-        return "minecraft.jar".to_owned();
-    }
-    let mut parts = file.split(':');
-    let path = parts.next().expect("No path").replace(".", "/");
-    let name = parts.next().expect("No name");
-    let version = parts.next().expect("No version");
-    let mut filename = format!("{}/{}/{}/{}-{}.jar", path, name, version, name, version);
-    if let Some(more) = parts.next() {
-        filename.push_str(&format!("-{}", more));
+    if let Err(e) = result {
+        eprintln!("❌ Failed to create manifest: {}", e);
+        exit(1);
     }
-    if file == "minecraft.jar" {
-        filename
-    } else {
-        format!("libraries/{}", filename)
-    }
-}
-
-async fn java_downloader(
-    selected_java_manifest: SelectedJavaManifest,
-    name: &str,
-    platform: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Called!");
-    let semaphore = Arc::new(Semaphore::new(4));
-
-    let mut handles = vec![];
-
-    for (path, entry) in selected_java_manifest.files {
-        match entry {
-            FileEntry::Directory => {
-                println!("[IGNORED] Пустая папка: {}", path);
-            }
-            FileEntry::File {
-                downloads,
-                executable,
-            } => {
-                let permit = Arc::clone(&semaphore).acquire_owned().await.unwrap();
-                let path = format!("{}/{}/{}", platform, name, path);
-                let url = downloads.raw.url.clone();
-
-                let handle = tokio::spawn(async move {
-                    let _permit = permit; // удерживаем семафор до конца задачи
-
-                    let file_path = Path::new(&path).parent().unwrap().to_path_buf();
-                    if !file_path.exists() {
-                        fs::create_dir_all(&file_path)
-                            .await
-                            .expect("Failed to create parent directories");
-                    }
-
-                    let response = reqwest::get(&url)
-                        .await
-                        .expect("Failed to download file")
-                        .bytes()
-                        .await
-                        .expect("Failed to read bytes");
-
-                    let mut file = fs::File::create(&path)
-                        .await
-                        .expect("Failed to create file");
-
-                    file.write_all(&response)
-                        .await
-                        .expect("Failed to write file");
-
-                    file.flush().await.expect("Failed to flush file");
 
-                    println!("✅ Saved: {} <- {}", path, url);
-                });
-
-                handles.push(handle);
-            }
-            FileEntry::Ignored => {}
+    if args.download_assets {
+        println!("Downloading assets...");
+        if let Err(e) = assets::download_assets(&args.server_name, &args.mc_version).await {
+            eprintln!("❌ Failed to download assets: {}", e);
+            exit(1);
         }
     }
 
-    // Дождаться всех задач
-    let results = join_all(handles).await;
-
-    // Проверить результат каждой задачи
-    for res in results {
-        res?; // пропускаем ошибки из задач
+    // С --locked lock-файл остаётся как есть
+    if !lock::is_locked() {
+        if let Err(e) = lock::write(&args.server_name, &args).await {
+            eprintln!("❌ Failed to write {}: {}", lock::LOCK_FILE, e);
+            exit(1);
+        }
     }
-
-    Ok(())
 }
 
-async fn download_java(version: u64) -> Result<(), Box<dyn std::error::Error>> {
-    let javas_manifest = reqwest::get("https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json").await?
-    .json::<JavaRuntime>()
-    .await
-    .unwrap();
-
-    let mut windows_url = "";
-    let mut windows_arm64_url = "";
-    let mut mac_os_url = "";
-    let mut mac_os_arm64_url = "";
-    let mut linux_url = "";
-    let mut name = "";
-
-    if version == 17 {
-        windows_url = &javas_manifest.windows_x64.java_runtime_gamma[0]
-            .manifest
-            .url;
-        windows_arm64_url = &javas_manifest.windows_arm64.java_runtime_gamma[0]
-            .manifest
-            .url;
-        mac_os_url = &javas_manifest.mac_os.java_runtime_gamma[0].manifest.url;
-        mac_os_arm64_url = &javas_manifest.mac_os_arm64.java_runtime_gamma[0]
-            .manifest
-            .url;
-        linux_url = &javas_manifest.linux.java_runtime_gamma[0].manifest.url;
-        name = "java_runtime_gamma"
-    } else {
-        windows_url = &javas_manifest.windows_x64.java_runtime_delta[0]
-            .manifest
-            .url;
-        windows_arm64_url = &javas_manifest.windows_arm64.java_runtime_delta[0]
-            .manifest
-            .url;
-        mac_os_url = &javas_manifest.mac_os.java_runtime_delta[0].manifest.url;
-        mac_os_arm64_url = &javas_manifest.mac_os_arm64.java_runtime_delta[0]
-            .manifest
-            .url;
-        linux_url = &javas_manifest.linux.java_runtime_delta[0].manifest.url;
-        name = "java_runtime_delta"
-    }
-
-    let windows = reqwest::get(windows_url)
-        .await?
-        .json::<SelectedJavaManifest>()
-        .await
-        .unwrap();
-    let windows_arm64 = reqwest::get(windows_arm64_url)
-        .await?
-        .json::<SelectedJavaManifest>()
-        .await
-        .unwrap();
-    let mac_os = reqwest::get(mac_os_url)
-        .await?
-        .json::<SelectedJavaManifest>()
-        .await
-        .unwrap();
-    let mac_os_arm64 = reqwest::get(mac_os_arm64_url)
-        .await?
-        .json::<SelectedJavaManifest>()
-        .await
-        .unwrap();
-    let linux = reqwest::get(linux_url)
-        .await?
-        .json::<SelectedJavaManifest>()
-        .await
-        .unwrap();
-
-    let platforms = vec![
-        ("windows", windows),
-        ("windows_arm64", windows_arm64),
-        ("mac_os", mac_os),
-        ("mac_os_arm64", mac_os_arm64),
-        ("linux", linux),
-    ];
-
-    for (platform_name, platform) in platforms {
-        match java_downloader(platform, name, platform_name).await {
-            Ok(_) => println!("Successfully downloaded for {}", platform_name),
-            Err(e) => eprintln!("Error downloading for {}: {:?}", platform_name, e),
+fn run_cache_command(action: CacheCommand, cache_dir: Option<PathBuf>) {
+    let Some(root) = cache_dir else {
+        eprintln!("❌ Cache directory not found, pass --cache-dir");
+        exit(1);
+    };
+
+    match action {
+        CacheCommand::Prune {
+            older_than_days,
+            all,
+        } => {
+            let older_than = (!all).then(|| Duration::from_secs(older_than_days * 24 * 60 * 60));
+
+            match cache::prune(&root, older_than) {
+                Ok((removed, freed)) => println!(
+                    "Removed {} object(s), freed {:.1} MB from {}",
+                    removed,
+                    freed as f64 / 1024.0 / 1024.0,
+                    root.display()
+                ),
+                Err(e) => {
+                    eprintln!("❌ Failed to prune cache: {}", e);
+                    exit(1);
+                }
+            }
         }
     }
-
-    Ok(())
 }
@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    java,
+    types::{FileObject, LibraryObject, NekoManifest},
+    utils::sha1_hex,
+};
+
+/// Сохраняет manifest.json. Библиотеки с одинаковым путём склеиваются,
+/// а недостающие sha1/size досчитываются по скачанным файлам.
+pub async fn write_manifest(
+    server_name: &str,
+    mut neko_manifest: NekoManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut by_path: HashMap<String, LibraryObject> = HashMap::new();
+
+    for lib in neko_manifest.libraries.drain() {
+        match by_path.remove(&lib.path) {
+            Some(existing) => {
+                let merged = merge_libraries(existing, lib);
+                by_path.insert(merged.path.clone(), merged);
+            }
+            None => {
+                by_path.insert(lib.path.clone(), lib);
+            }
+        }
+    }
+
+    neko_manifest.libraries = by_path
+        .into_values()
+        .map(|lib| fill_hash(server_name, lib))
+        .collect();
+
+    neko_manifest.files = index_files(server_name, &neko_manifest.verify, &neko_manifest.ignore)?;
+    neko_manifest.java = java::runtimes();
+
+    let mut manifest = File::create(format!("{}/manifest.json", server_name))
+        .await
+        .expect("Failed to create manifest");
+
+    let manifest_json =
+        serde_json::to_string(&neko_manifest).expect("Failed to serialize manifest");
+
+    manifest
+        .write_all(manifest_json.as_bytes())
+        .await
+        .expect("Failed to write manifest to file");
+
+    Ok(())
+}
+
+fn merge_libraries(first: LibraryObject, second: LibraryObject) -> LibraryObject {
+    let mut platforms = first.platforms;
+    platforms.extend(second.platforms);
+
+    LibraryObject {
+        extract: first.extract.or(second.extract),
+        sha1: first.sha1.or(second.sha1),
+        size: first.size.or(second.size),
+        ..LibraryObject::new(first.path, platforms)
+    }
+}
+
+fn fill_hash(server_name: &str, mut lib: LibraryObject) -> LibraryObject {
+    if lib.sha1.is_some() && lib.size.is_some() {
+        return lib;
+    }
+
+    match std::fs::read(format!("{}/{}", server_name, lib.path)) {
+        Ok(bytes) => {
+            lib.sha1 = Some(sha1_hex(&bytes));
+            lib.size = Some(bytes.len() as u64);
+        }
+        Err(_) => println!("[NO HASH] {}: file not found", lib.path),
+    }
+
+    lib
+}
+
+/// Обходит каждую папку/файл из `verify`, пропуская всё, что попадает под `ignore`
+fn index_files(
+    server_name: &str,
+    verify: &[String],
+    ignore: &[String],
+) -> Result<BTreeMap<String, FileObject>, Box<dyn std::error::Error>> {
+    let root = Path::new(server_name);
+    let mut files = BTreeMap::new();
+    let mut pending: Vec<String> = verify.to_vec();
+
+    while let Some(relative) = pending.pop() {
+        if is_ignored(&relative, ignore) {
+            continue;
+        }
+
+        let path = root.join(&relative);
+
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                pending.push(format!("{}/{}", relative.trim_end_matches('/'), name));
+            }
+        } else if path.is_file() {
+            let bytes = std::fs::read(&path)?;
+            files.insert(
+                relative,
+                FileObject {
+                    sha1: sha1_hex(&bytes),
+                    size: bytes.len() as u64,
+                },
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_ignored(relative: &str, ignore: &[String]) -> bool {
+    ignore.iter().any(|ignored| {
+        let ignored = ignored.trim_end_matches('/');
+        relative == ignored || relative.starts_with(&format!("{}/", ignored))
+    })
+}
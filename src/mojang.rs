@@ -1,127 +1,460 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Mutex,
+};
 
 use crate::{
-    resolve_maven,
+    http::{get_bytes, get_index, resolve},
+    lock::Resolved,
+    rules::{rules_allow, RuleTarget},
     types::{
-        Libraries, LibraryObject, MojangClientManifest, MojangResult, MojangVersionManifest, OsType,
+        Libraries, LibraryObject, MojangArgument, MojangArgumentValue, MojangArtifact,
+        MojangClientManifest, MojangLibrary, MojangLibraryRule, MojangOs, MojangResult,
+        MojangVersionManifest, OsType, Platform, RuledArgument,
     },
+    utils::{library_path, sha1_hex},
 };
 
-pub async fn parse_mojang(game_version: String) -> MojangResult {
-    let get_version_manifest =
-        reqwest::get("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json")
-            .await
-            .unwrap()
-            .json::<MojangVersionManifest>()
-            .await
-            .unwrap();
-
-    let version_manifest = get_version_manifest
-        .versions
-        .into_iter()
-        .find(|v| v.id == game_version)
-        .expect("Version not found");
-
-    let mojang_manifest = reqwest::get(&version_manifest.url)
-        .await
-        .unwrap()
-        .json::<MojangClientManifest>()
-        .await
-        .unwrap();
+const VERSION_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+
+/// JSON версий, уже скачанные за запуск: манифест нужен и библиотекам, и Java,
+/// и процессорам, и ассетам, а перепроверять его каждый раз незачем
+static CLIENT_MANIFESTS: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+pub async fn get_client_manifest(
+    game_version: &str,
+) -> Result<MojangClientManifest, Box<dyn std::error::Error>> {
+    let cached = CLIENT_MANIFESTS.lock().unwrap().get(game_version).cloned();
+    let body = match cached {
+        Some(body) => body,
+        None => {
+            let body = fetch_client_manifest(game_version).await?;
+            CLIENT_MANIFESTS
+                .lock()
+                .unwrap()
+                .insert(game_version.to_owned(), body.clone());
+            body
+        }
+    };
+
+    serde_json::from_slice(&body).map_err(|e| format!("{}: {}", game_version, e).into())
+}
+
+async fn fetch_client_manifest(game_version: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let version = resolve(
+        &format!("{}#{}", VERSION_MANIFEST_URL, game_version),
+        || async {
+            let version_manifest: MojangVersionManifest = get_index(VERSION_MANIFEST_URL).await?;
+
+            Ok(version_manifest
+                .versions
+                .into_iter()
+                .find(|v| v.id == game_version)
+                .map(|v| Resolved {
+                    url: v.url,
+                    sha1: Some(v.sha1),
+                    sha256: None,
+                    size: None,
+                    version: None,
+                }))
+        },
+    )
+    .await?
+    .ok_or_else(|| format!("Version {} not found", game_version))?;
+
+    let body = get_bytes(&version.url).await?;
+    if version.sha1.as_deref() != Some(sha1_hex(&body).as_str()) {
+        return Err(format!("{}: sha1 does not match the version manifest", version.url).into());
+    }
+
+    Ok(body)
+}
+
+pub async fn parse_mojang(
+    game_version: String,
+) -> Result<MojangResult, Box<dyn std::error::Error>> {
+    let mojang_manifest = get_client_manifest(&game_version).await?;
 
     let mut hash_libs = HashSet::new();
 
     hash_libs.insert(LibraryObject {
-        path: "minecraft.jar".to_owned(),
-        os: [OsType::Windows, OsType::Linux, OsType::MacOs].to_vec(),
+        sha1: Some(mojang_manifest.downloads.client.sha1.clone()),
+        size: mojang_manifest.downloads.client.size,
+        ..LibraryObject::new("minecraft.jar".to_owned(), Platform::ALL.to_vec())
     });
-    
+
+    let mut mojang_libs: Vec<Libraries> = Vec::new();
+
     for lib in &mojang_manifest.libraries {
-        if lib.downloads.artifact.path.is_none() {
+        let platforms: Vec<Platform> = Platform::ALL
+            .into_iter()
+            .filter(|platform| allowed_on(lib, platform))
+            .collect();
+
+        if platforms.is_empty() {
             continue;
         }
 
-        let mut oss = HashSet::new();
-        oss.extend([OsType::Windows, OsType::Linux, OsType::MacOs]);
+        // Нативки старых версий лежат в classifiers, а не в artifact
+        for (native_platforms, classifier, artifact) in native_classifiers(lib, &platforms) {
+            let name = format!("{}:{}", lib.name, classifier);
 
-        if !lib.rules.is_none() {
-            for rule in lib.rules.as_ref().unwrap() {
-                if rule.action == "disallow" {
-                    oss.remove(&rule.os.name);
-                }
-            }
-        }
+            hash_libs.insert(LibraryObject {
+                sha1: Some(artifact.sha1.clone()),
+                size: artifact.size,
+                extract: Some(lib.extract.clone().unwrap_or_default()),
+                ..LibraryObject::new(library_path(&name), native_platforms)
+            });
 
-        let includes_windows = lib
-            .downloads
-            .artifact
-            .path
-            .clone()
-            .unwrap()
-            .contains("windows");
-        let includes_linux = lib
-            .downloads
-            .artifact
-            .path
-            .clone()
-            .unwrap()
-            .contains("linux");
-        let includes_osx = lib
-            .downloads
-            .artifact
-            .path
-            .clone()
-            .unwrap()
-            .contains("macos");
-
-        if includes_windows {
-            oss.remove(&OsType::Linux);
-            oss.remove(&OsType::MacOs);
-        } else if includes_linux {
-            oss.remove(&OsType::MacOs);
-            oss.remove(&OsType::Windows);
-        } else if includes_osx {
-            oss.remove(&OsType::Linux);
-            oss.remove(&OsType::Windows);
+            mojang_libs.push(Libraries {
+                name,
+                url: artifact.url.clone(),
+                sha1: Some(artifact.sha1.clone()),
+                size: artifact.size,
+            });
         }
 
-        let mut os_vec = Vec::new();
+        let artifact = match &lib.downloads.artifact {
+            Some(artifact) if artifact.path.is_some() => artifact,
+            _ => continue,
+        };
 
-        if oss.contains(&OsType::Windows) {
-            os_vec.push(OsType::Windows);
-        }
-        if oss.contains(&OsType::Linux) {
-            os_vec.push(OsType::Linux);
-        }
-        if oss.contains(&OsType::MacOs) {
-            os_vec.push(OsType::MacOs);
+        // Нативки 1.19+ - отдельные библиотеки, платформа видна по классификатору
+        let platforms: Vec<Platform> = match classifier_platforms(&lib.name) {
+            Some(classifier) => platforms
+                .into_iter()
+                .filter(|platform| classifier.contains(platform))
+                .collect(),
+            None => platforms,
+        };
+
+        if platforms.is_empty() {
+            continue;
         }
 
         hash_libs.insert(LibraryObject {
-            path: format!("{}", resolve_maven(&lib.name)),
-            os: os_vec,
+            sha1: Some(artifact.sha1.clone()),
+            size: artifact.size,
+            ..LibraryObject::new(library_path(&lib.name), platforms)
         });
-    }
 
-    let mut mojang_libs: Vec<Libraries> = mojang_manifest
-        .libraries
-        .into_iter()
-        .map(|lib| Libraries {
+        mojang_libs.push(Libraries {
             name: lib.name.clone(),
-            url: lib.downloads.artifact.url.clone(),
-            sha1: Some(lib.downloads.artifact.sha1.clone()),
-        })
-        .collect();
+            url: artifact.url.clone(),
+            sha1: Some(artifact.sha1.clone()),
+            size: artifact.size,
+        });
+    }
 
     mojang_libs.push(Libraries {
         name: "minecraft.jar".to_owned(),
         url: mojang_manifest.downloads.client.url,
         sha1: Some(mojang_manifest.downloads.client.sha1),
+        size: mojang_manifest.downloads.client.size,
     });
 
-    MojangResult {
+    let ((game, game_rules), (jvm, jvm_rules)) = match mojang_manifest.arguments {
+        Some(arguments) => (
+            split_arguments(arguments.game),
+            split_arguments(arguments.jvm),
+        ),
+        None => (
+            (
+                mojang_manifest
+                    .minecraftArguments
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(|arg| arg.to_owned())
+                    .collect(),
+                Vec::new(),
+            ),
+            legacy_jvm_arguments(),
+        ),
+    };
+
+    Ok(MojangResult {
         libraries: mojang_libs,
         hash_libs,
         asset_index: mojang_manifest.assetIndex.id,
+        main_class: mojang_manifest.mainClass,
+        game,
+        jvm,
+        game_rules,
+        jvm_rules,
+    })
+}
+
+fn allowed_on(lib: &MojangLibrary, platform: &Platform) -> bool {
+    rules_allow(
+        lib.rules.as_ref(),
+        &RuleTarget {
+            os: &platform.os(),
+            arch: platform.arch(),
+        },
+    )
+}
+
+/// Платформы из классификатора вида `natives-macos-arm64`, `natives-windows-x86` или `linux-aarch_64`.
+/// Классификатор без архитектуры означает x86_64.
+fn classifier_platforms(name: &str) -> Option<Vec<Platform>> {
+    let classifier = name.split(':').nth(3)?;
+
+    let os = if classifier.contains("windows") {
+        OsType::Windows
+    } else if classifier.contains("linux") {
+        OsType::Linux
+    } else if classifier.contains("macos") || classifier.contains("osx") {
+        OsType::MacOs
+    } else {
+        return None;
+    };
+
+    let arch = if classifier.contains("arm64") || classifier.contains("aarch_64") {
+        "arm64"
+    } else if classifier.ends_with("-x86") || classifier.ends_with("-i386") {
+        "x86"
+    } else {
+        "x86_64"
+    };
+
+    Some(
+        Platform::ALL
+            .into_iter()
+            .filter(|platform| platform.os() == os && platform.arch() == arch)
+            .collect(),
+    )
+}
+
+/// Классификаторы нативок библиотеки с платформами, под которые они подходят.
+/// `${arch}` раскрывается в 32 (x86) и 64 (x86_64, arm64 через эмуляцию).
+fn native_classifiers<'a>(
+    lib: &'a MojangLibrary,
+    allowed: &[Platform],
+) -> Vec<(Vec<Platform>, String, &'a MojangArtifact)> {
+    let (natives, classifiers) = match (&lib.natives, &lib.downloads.classifiers) {
+        (Some(natives), Some(classifiers)) => (natives, classifiers),
+        _ => return Vec::new(),
+    };
+
+    let mut result = Vec::new();
+
+    for (os, template) in natives {
+        let os_platforms = allowed.iter().filter(|platform| platform.os() == *os);
+
+        let variants: Vec<(String, Vec<Platform>)> = if template.contains("${arch}") {
+            let (x86, x64): (Vec<Platform>, Vec<Platform>) =
+                os_platforms.partition(|platform| platform.arch() == "x86");
+            vec![
+                (template.replace("${arch}", "32"), x86),
+                (template.replace("${arch}", "64"), x64),
+            ]
+        } else {
+            vec![(template.clone(), os_platforms.copied().collect())]
+        };
+
+        for (classifier, platforms) in variants {
+            if platforms.is_empty() {
+                continue;
+            }
+
+            if let Some(artifact) = classifiers.get(&classifier) {
+                result.push((platforms, classifier, artifact));
+            }
+        }
+    }
+
+    result
+}
+
+/// Делит аргументы на обычные и с правилами
+fn split_arguments(arguments: Vec<MojangArgument>) -> (Vec<String>, Vec<RuledArgument>) {
+    let mut plain = Vec::new();
+    let mut ruled = Vec::new();
+
+    for arg in arguments {
+        match arg {
+            MojangArgument::Plain(value) => plain.push(value),
+            MojangArgument::Ruled { rules, value } => ruled.push(RuledArgument {
+                rules,
+                value: match value {
+                    MojangArgumentValue::Single(value) => vec![value],
+                    MojangArgumentValue::Multiple(values) => values,
+                },
+            }),
+        }
+    }
+
+    (plain, ruled)
+}
+
+/// В версиях до 1.13 JVM-аргументов в манифесте нет, лаунчер Mojang подставляет их сам
+fn legacy_jvm_arguments() -> (Vec<String>, Vec<RuledArgument>) {
+    (
+        vec![
+            "-Djava.library.path=${natives_directory}".to_owned(),
+            "-cp".to_owned(),
+            "${classpath}".to_owned(),
+        ],
+        vec![RuledArgument {
+            rules: vec![MojangLibraryRule {
+                action: "allow".to_owned(),
+                os: Some(MojangOs {
+                    name: Some(OsType::MacOs),
+                    arch: None,
+                    version: None,
+                }),
+                features: None,
+            }],
+            value: vec!["-XstartOnFirstThread".to_owned()],
+        }],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn classifier_platforms_from_name() {
+        let table: [(&str, Option<Vec<Platform>>); 10] = [
+            // Отдельные библиотеки-нативки lwjgl 3.3.1 из 1.19.json
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-linux",
+                Some(vec![Platform::Linux]),
+            ),
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-macos",
+                Some(vec![Platform::MacOs]),
+            ),
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-macos-arm64",
+                Some(vec![Platform::MacOsArm64]),
+            ),
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-windows",
+                Some(vec![Platform::WindowsX64]),
+            ),
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-windows-arm64",
+                Some(vec![Platform::WindowsArm64]),
+            ),
+            (
+                "org.lwjgl:lwjgl:3.3.1:natives-windows-x86",
+                Some(vec![Platform::WindowsX86]),
+            ),
+            // Классификаторы netty в стиле Maven
+            (
+                "io.netty:netty-transport-native-epoll:4.1.97.Final:linux-aarch_64",
+                Some(vec![Platform::LinuxArm64]),
+            ),
+            (
+                "io.netty:netty-transport-native-epoll:4.1.97.Final:linux-x86_64",
+                Some(vec![Platform::Linux]),
+            ),
+            // Без классификатора или с классификатором не про платформу
+            ("org.lwjgl:lwjgl:3.3.1", None),
+            ("net.minecraftforge:mergetool:1.1.5:api", None),
+        ];
+
+        for (name, expected) in table {
+            assert_eq!(classifier_platforms(name), expected, "{}", name);
+        }
+    }
+
+    fn library(json: &str) -> MojangLibrary {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn classifiers(lib: &MojangLibrary, allowed: &[Platform]) -> BTreeMap<String, Vec<Platform>> {
+        native_classifiers(lib, allowed)
+            .into_iter()
+            .map(|(platforms, classifier, _)| (classifier, platforms))
+            .collect()
+    }
+
+    #[test]
+    fn expands_arch_in_native_classifiers() {
+        // twitch-platform из 1.8.9.json
+        let twitch = library(
+            r#"{
+                "name": "tv.twitch:twitch-platform:6.5",
+                "downloads": {
+                    "classifiers": {
+                        "natives-osx": { "sha1": "a", "size": 1, "url": "https://example/osx.jar" },
+                        "natives-windows-32": { "sha1": "b", "size": 1, "url": "https://example/32.jar" },
+                        "natives-windows-64": { "sha1": "c", "size": 1, "url": "https://example/64.jar" }
+                    }
+                },
+                "natives": { "osx": "natives-osx", "windows": "natives-windows-${arch}" },
+                "rules": [{ "action": "allow" }, { "action": "disallow", "os": { "name": "linux" } }]
+            }"#,
+        );
+
+        let allowed: Vec<Platform> = Platform::ALL
+            .into_iter()
+            .filter(|platform| allowed_on(&twitch, platform))
+            .collect();
+
+        assert_eq!(
+            classifiers(&twitch, &allowed),
+            BTreeMap::from([
+                (
+                    "natives-osx".to_owned(),
+                    vec![Platform::MacOs, Platform::MacOsArm64]
+                ),
+                ("natives-windows-32".to_owned(), vec![Platform::WindowsX86]),
+                (
+                    "natives-windows-64".to_owned(),
+                    vec![Platform::WindowsX64, Platform::WindowsArm64]
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn native_classifiers_without_arch() {
+        // lwjgl-platform из 1.8.9.json
+        let lwjgl = library(
+            r#"{
+                "name": "org.lwjgl.lwjgl:lwjgl-platform:2.9.4-nightly-20150209",
+                "downloads": {
+                    "classifiers": {
+                        "natives-linux": { "sha1": "a", "size": 1, "url": "https://example/linux.jar" },
+                        "natives-osx": { "sha1": "b", "size": 1, "url": "https://example/osx.jar" },
+                        "natives-windows": { "sha1": "c", "size": 1, "url": "https://example/windows.jar" }
+                    }
+                },
+                "natives": { "linux": "natives-linux", "osx": "natives-osx", "windows": "natives-windows" },
+                "extract": { "exclude": ["META-INF/"] }
+            }"#,
+        );
+
+        assert_eq!(
+            classifiers(
+                &lwjgl,
+                &[Platform::Linux, Platform::LinuxArm64, Platform::WindowsX86]
+            ),
+            BTreeMap::from([
+                (
+                    "natives-linux".to_owned(),
+                    vec![Platform::Linux, Platform::LinuxArm64]
+                ),
+                ("natives-windows".to_owned(), vec![Platform::WindowsX86]),
+            ])
+        );
+
+        // Обычные библиотеки нативок не дают
+        let plain = library(
+            r#"{
+                "name": "com.mojang:realms:1.7.59",
+                "downloads": {
+                    "artifact": { "path": "com/mojang/realms/1.7.59/realms-1.7.59.jar", "sha1": "a", "size": 1, "url": "https://example/realms.jar" }
+                }
+            }"#,
+        );
+        assert!(native_classifiers(&plain, &Platform::ALL).is_empty());
     }
 }
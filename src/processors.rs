@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, create_dir_all, File},
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use tokio::process::Command;
+use zip::ZipArchive;
+
+use crate::{
+    download::{download_all, DownloadTask},
+    forgeinstaller::{ForgeClientManifest, ForgeLibrary, InstallProfile, Processor},
+    java::resolve_java,
+    mojang::get_client_manifest,
+    utils::{download_loader_installer, resolve_maven, sha1_hex},
+};
+
+/// Установка Forge/NeoForge 1.13+ без официального установщика: качаем библиотеки
+/// из version.json и install_profile.json и сами запускаем процессоры клиента
+/// (binpatch, маппинги, jar-splitter), сверяя их выходные файлы с install_profile.json.
+pub async fn install_modern_loader(
+    loader_type: &str,
+    mc_version: &str,
+    loader_version: &str,
+    server_name: &str,
+    manifest: &ForgeClientManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let installer_path = download_loader_installer(loader_type, mc_version, loader_version).await?;
+    let mut installer = ZipArchive::new(File::open(&installer_path)?)?;
+
+    let mut profile_json = String::new();
+    installer
+        .by_name("install_profile.json")?
+        .read_to_string(&mut profile_json)?;
+    let profile: InstallProfile = serde_json::from_str(&profile_json)?;
+
+    let root = env::current_dir()?.join(server_name);
+    let libraries_dir = root.join("libraries");
+
+    let mut tasks = Vec::new();
+
+    for lib in manifest.libraries.iter().chain(&profile.libraries) {
+        if let Some(task) = library_task(lib, &libraries_dir, &mut installer)? {
+            tasks.push(task);
+        }
+    }
+
+    download_all(tasks).await?;
+
+    // Файлы из установщика (`/data/client.lzma`) распаковываются во временную папку
+    let work_dir = env::temp_dir().join(format!(
+        "neko-{}-{}-{}-{}",
+        loader_type,
+        mc_version,
+        loader_version,
+        std::process::id()
+    ));
+    create_dir_all(&work_dir)?;
+
+    let result = run_processors(
+        &profile,
+        &mut installer,
+        &installer_path,
+        &root,
+        &work_dir,
+        mc_version,
+    )
+    .await;
+
+    let _ = fs::remove_dir_all(&work_dir);
+
+    result
+}
+
+/// Библиотека без URL лежит внутри установщика в `maven/`, а если нет и там - её создаст процессор
+fn library_task(
+    lib: &ForgeLibrary,
+    libraries_dir: &Path,
+    installer: &mut ZipArchive<File>,
+) -> Result<Option<DownloadTask>, Box<dyn std::error::Error>> {
+    let artifact = match &lib.downloads {
+        Some(downloads) => &downloads.artifact,
+        None => return Ok(None),
+    };
+
+    let path = libraries_dir.join(&artifact.path);
+
+    if artifact.url.is_empty() {
+        if extract(installer, &format!("maven/{}", artifact.path), &path)? {
+            println!("{}: installer -> {:?}", lib.name, path);
+        }
+        return Ok(None);
+    }
+
+    Ok(Some(DownloadTask {
+        name: lib.name.clone(),
+        url: artifact.url.clone(),
+        path,
+        sha1: Some(artifact.sha1.clone()).filter(|sha1| !sha1.is_empty()),
+        size: artifact.size,
+    }))
+}
+
+async fn run_processors(
+    profile: &InstallProfile,
+    installer: &mut ZipArchive<File>,
+    installer_path: &Path,
+    root: &Path,
+    work_dir: &Path,
+    mc_version: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let libraries_dir = root.join("libraries");
+
+    let mut data: HashMap<String, String> = HashMap::new();
+
+    for (key, value) in &profile.data {
+        let value = &value.client;
+
+        let resolved = if let Some(coords) = maven_reference(value) {
+            path_string(&libraries_dir.join(resolve_maven(coords)))
+        } else if let Some(literal) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            literal.to_owned()
+        } else if let Some(entry) = value.strip_prefix('/') {
+            let path = work_dir.join(entry);
+            if !extract(installer, entry, &path)? {
+                return Err(format!("{} not found in installer ({})", entry, key).into());
+            }
+            path_string(&path)
+        } else {
+            value.clone()
+        };
+
+        data.insert(key.clone(), resolved);
+    }
+
+    data.insert("SIDE".to_owned(), "client".to_owned());
+    data.insert(
+        "MINECRAFT_JAR".to_owned(),
+        path_string(&root.join("minecraft.jar")),
+    );
+    data.insert("MINECRAFT_VERSION".to_owned(), mc_version.to_owned());
+    data.insert("ROOT".to_owned(), path_string(root));
+    data.insert("INSTALLER".to_owned(), path_string(installer_path));
+    data.insert("LIBRARY_DIR".to_owned(), path_string(&libraries_dir));
+
+    let processors: Vec<&Processor> = profile
+        .processors
+        .iter()
+        .filter(|processor| match &processor.sides {
+            Some(sides) => sides.iter().any(|side| side == "client"),
+            None => true,
+        })
+        .collect();
+
+    // Java ищем только когда процессор действительно надо запустить
+    let mut java: Option<PathBuf> = None;
+
+    for (index, processor) in processors.iter().enumerate() {
+        let args = processor
+            .args
+            .iter()
+            .map(|arg| resolve_argument(arg, &data, &libraries_dir))
+            .collect::<Result<Vec<String>, String>>()?;
+
+        let mut outputs = Vec::new();
+        for (file, sha1) in &processor.outputs {
+            outputs.push((
+                resolve_argument(file, &data, &libraries_dir)?,
+                resolve_argument(sha1, &data, &libraries_dir)?,
+            ));
+        }
+
+        let label = format!(
+            "[PROCESSOR {}/{}] {}",
+            index + 1,
+            processors.len(),
+            processor.jar
+        );
+
+        if !outputs.is_empty() && outputs_valid(&outputs).is_ok() {
+            println!("{}: outputs are up to date", label);
+            continue;
+        }
+
+        if task_argument(&args) == Some("DOWNLOAD_MOJMAPS") {
+            println!("{}: downloading client mappings", label);
+            download_mojmaps(mc_version, &args).await?;
+        } else {
+            let java = match &java {
+                Some(java) => java,
+                None => java.insert(resolve_java(mc_version, root).await?),
+            };
+
+            println!("{}", label);
+            run_processor(java, processor, &args, &libraries_dir)
+                .await
+                .map_err(|e| format!("{}: {}", label, e))?;
+        }
+
+        outputs_valid(&outputs).map_err(|e| format!("{}: {}", label, e))?;
+    }
+
+    Ok(())
+}
+
+/// `[group:artifact:version]` -> координаты
+fn maven_reference(value: &str) -> Option<&str> {
+    value.strip_prefix('[')?.strip_suffix(']')
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// `{KEY}` -> значение из `data`, `[coords]` -> путь в libraries, остальное как есть
+fn resolve_argument(
+    arg: &str,
+    data: &HashMap<String, String>,
+    libraries_dir: &Path,
+) -> Result<String, String> {
+    if let Some(key) = arg.strip_prefix('{').and_then(|a| a.strip_suffix('}')) {
+        return data
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("Unknown install_profile data key {}", key));
+    }
+
+    if let Some(coords) = maven_reference(arg) {
+        return Ok(path_string(&libraries_dir.join(resolve_maven(coords))));
+    }
+
+    Ok(arg.to_owned())
+}
+
+/// Значение `--task` у installertools
+fn task_argument(args: &[String]) -> Option<&str> {
+    let index = args.iter().position(|arg| arg == "--task")?;
+    args.get(index + 1).map(|task| task.as_str())
+}
+
+/// installertools качает маппинги сам, мимо кеша, зеркал и neko.lock - делаем это за него
+async fn download_mojmaps(
+    mc_version: &str,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = args
+        .iter()
+        .position(|arg| arg == "--output")
+        .and_then(|index| args.get(index + 1))
+        .ok_or("DOWNLOAD_MOJMAPS without --output")?;
+
+    let mappings = get_client_manifest(mc_version)
+        .await?
+        .downloads
+        .client_mappings
+        .ok_or_else(|| format!("Version {} has no client mappings", mc_version))?;
+
+    download_all(vec![DownloadTask {
+        name: "client_mappings".to_owned(),
+        url: mappings.url,
+        path: PathBuf::from(output),
+        sha1: Some(mappings.sha1),
+        size: mappings.size,
+    }])
+    .await
+}
+
+async fn run_processor(
+    java: &Path,
+    processor: &Processor,
+    args: &[String],
+    libraries_dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let jar = libraries_dir.join(resolve_maven(&processor.jar));
+    let main_class = main_class(&jar)?;
+
+    let mut classpath = vec![jar];
+    classpath.extend(
+        processor
+            .classpath
+            .iter()
+            .map(|coords| libraries_dir.join(resolve_maven(coords))),
+    );
+
+    if let Some(missing) = classpath.iter().find(|path| !path.exists()) {
+        return Err(format!("Missing processor library {:?}", missing).into());
+    }
+
+    let status = Command::new(java)
+        .arg("-cp")
+        .arg(env::join_paths(&classpath)?)
+        .arg(&main_class)
+        .args(args)
+        .status()
+        .await
+        .map_err(|e| format!("Failed to run {:?}: {}", java, e))?;
+
+    if !status.success() {
+        return Err(format!("{} exited with {}", main_class, status).into());
+    }
+
+    Ok(())
+}
+
+/// Main-Class из META-INF/MANIFEST.MF
+fn main_class(jar: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(File::open(jar)?)?;
+    let manifest = BufReader::new(archive.by_name("META-INF/MANIFEST.MF")?);
+
+    for line in manifest.lines() {
+        if let Some(class) = line?.strip_prefix("Main-Class:") {
+            return Ok(class.trim().to_owned());
+        }
+    }
+
+    Err(format!("No Main-Class in {:?}", jar).into())
+}
+
+/// Все выходы процессора на месте и с ожидаемым SHA-1. Битые выходы удаляются
+fn outputs_valid(outputs: &[(String, String)]) -> Result<(), String> {
+    for (file, expected) in outputs {
+        let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+        let actual = sha1_hex(&bytes);
+
+        if !actual.eq_ignore_ascii_case(expected) {
+            let _ = fs::remove_file(file);
+            return Err(format!(
+                "{} has sha1 {}, expected {}",
+                file, actual, expected
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Распаковывает файл из установщика. `false`, если такого файла в нём нет
+fn extract(
+    installer: &mut ZipArchive<File>,
+    name: &str,
+    dest: &Path,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut entry = match installer.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    if let Some(parent) = dest.parent() {
+        create_dir_all(parent)?;
+    }
+
+    std::io::copy(&mut entry, &mut File::create(dest)?)?;
+
+    Ok(true)
+}
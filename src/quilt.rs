@@ -0,0 +1,16 @@
+use crate::{fabric::create_profile_manifest, http::get_json, types::FabricManifest};
+
+pub async fn create_quilt_manifest(
+    server_name: String,
+    quilt_version: String,
+    mc_version: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Профиль Quilt совпадает по формату с Fabric, отличаются хост, репозитории и mainClass
+    let quilt_manifest: FabricManifest = get_json(&format!(
+        "https://meta.quiltmc.org/v3/versions/loader/{}/{}/profile/json",
+        mc_version, quilt_version
+    ))
+    .await?;
+
+    create_profile_manifest(server_name, mc_version, quilt_manifest).await
+}
@@ -0,0 +1,129 @@
+use crate::types::{MojangLibraryRule, OsType};
+
+/// Платформа, для которой проверяются правила Mojang
+pub struct RuleTarget<'a> {
+    pub os: &'a OsType,
+    /// Архитектура в терминах Mojang: "x86", "x86_64", "arm64"
+    pub arch: &'a str,
+}
+
+/// Правила применяются по порядку, побеждает последнее совпавшее.
+/// Без правил библиотека разрешена, с правилами - запрещена, пока её не разрешат.
+pub fn rules_allow(rules: Option<&Vec<MojangLibraryRule>>, target: &RuleTarget) -> bool {
+    let rules = match rules {
+        Some(rules) => rules,
+        None => return true,
+    };
+
+    let mut allowed = false;
+
+    for rule in rules {
+        if rule_matches(rule, target) {
+            allowed = rule.action == "allow";
+        }
+    }
+
+    allowed
+}
+
+fn rule_matches(rule: &MojangLibraryRule, target: &RuleTarget) -> bool {
+    // features бывают только у аргументов, для библиотек такие правила не применяются
+    if rule.features.is_some() {
+        return false;
+    }
+
+    let os = match &rule.os {
+        Some(os) => os,
+        None => return true,
+    };
+
+    if let Some(name) = &os.name {
+        if name != target.os {
+            return false;
+        }
+    }
+
+    if let Some(arch) = &os.arch {
+        if arch != target.arch {
+            return false;
+        }
+    }
+
+    // Версия ОС клиента при сборке манифеста неизвестна (правила вида `^10\.` для Windows 10),
+    // поэтому правило с `os.version` не совпадает никогда
+    if os.version.is_some() {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allows(rules: &str, os: OsType, arch: &str) -> bool {
+        let rules: Vec<MojangLibraryRule> = serde_json::from_str(rules).unwrap();
+        rules_allow(Some(&rules), &RuleTarget { os: &os, arch })
+    }
+
+    #[test]
+    fn no_rules_allow_everything() {
+        let target = RuleTarget {
+            os: &OsType::Linux,
+            arch: "x86_64",
+        };
+        assert!(rules_allow(None, &target));
+        assert!(!allows("[]", OsType::Linux, "x86_64"));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        // lwjgl 2.9.4 из 1.12.2.json: везде, кроме macOS
+        let everywhere_but_mac =
+            r#"[{"action": "allow"}, {"action": "disallow", "os": {"name": "osx"}}]"#;
+        assert!(allows(everywhere_but_mac, OsType::Windows, "x86_64"));
+        assert!(allows(everywhere_but_mac, OsType::Linux, "x86_64"));
+        assert!(!allows(everywhere_but_mac, OsType::MacOs, "x86_64"));
+
+        // lwjgl 2.9.2-nightly из 1.12.2.json: только macOS
+        let only_mac = r#"[{"action": "allow", "os": {"name": "osx"}}]"#;
+        assert!(allows(only_mac, OsType::MacOs, "arm64"));
+        assert!(!allows(only_mac, OsType::Windows, "x86_64"));
+
+        let disallow_then_allow =
+            r#"[{"action": "disallow", "os": {"name": "osx"}}, {"action": "allow"}]"#;
+        assert!(allows(disallow_then_allow, OsType::MacOs, "x86_64"));
+    }
+
+    #[test]
+    fn matches_arch() {
+        let x86_only = r#"[{"action": "allow", "os": {"arch": "x86"}}]"#;
+        assert!(allows(x86_only, OsType::Windows, "x86"));
+        assert!(!allows(x86_only, OsType::Windows, "x86_64"));
+        assert!(!allows(x86_only, OsType::Windows, "arm64"));
+
+        let windows_arm = r#"[{"action": "allow", "os": {"name": "windows", "arch": "arm64"}}]"#;
+        assert!(allows(windows_arm, OsType::Windows, "arm64"));
+        assert!(!allows(windows_arm, OsType::Linux, "arm64"));
+    }
+
+    #[test]
+    fn rules_with_features_never_match() {
+        let demo = r#"[{"action": "allow", "features": {"is_demo_user": true}}]"#;
+        assert!(!allows(demo, OsType::Linux, "x86_64"));
+
+        let not_for_demo =
+            r#"[{"action": "allow"}, {"action": "disallow", "features": {"is_demo_user": true}}]"#;
+        assert!(allows(not_for_demo, OsType::Linux, "x86_64"));
+    }
+
+    #[test]
+    fn rules_with_os_version_never_match() {
+        let windows_10 = r#"[{"action": "allow", "os": {"name": "windows", "version": "^10\\."}}]"#;
+        assert!(!allows(windows_10, OsType::Windows, "x86_64"));
+
+        let except_old_mac = r#"[{"action": "allow"}, {"action": "disallow", "os": {"name": "osx", "version": "^10\\.5\\.\\d$"}}]"#;
+        assert!(allows(except_old_mac, OsType::MacOs, "x86_64"));
+    }
+}
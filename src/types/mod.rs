@@ -1,16 +1,129 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 
 // Cli types
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub build: Option<BuildArgs>,
+    /// Папка общего кеша (по умолчанию ~/.cache/neko-manifest)
+    #[arg(long, global = true)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Управление общим кешем загрузок
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Удалить объекты и ответы метаданных, которые не использовались последние N дней
+    Prune {
+        #[arg(long, default_value_t = 30)]
+        older_than_days: u64,
+        /// Очистить кеш полностью
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// Как файл из кеша попадает в папку сервера
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CacheLink {
+    Hardlink,
+    Reflink,
+    Copy,
+}
+
+/// Откуда берутся рантаймы Java
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JavaProvider {
+    /// java-runtime из лаунчера Mojang
+    Mojang,
+    /// Eclipse Temurin через api.adoptium.net
+    Adoptium,
+    /// Azul Zulu через api.azul.com
+    Zulu,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JavaImage {
+    Jre,
+    Jdk,
+}
+
+impl JavaImage {
+    /// Тип пакета в API Adoptium и Azul
+    pub fn name(&self) -> &'static str {
+        match self {
+            JavaImage::Jre => "jre",
+            JavaImage::Jdk => "jdk",
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
     pub server_name: String,
     pub loader: String,
     pub loader_version: String,
     pub mc_version: String,
     pub download_java: Option<String>,
+    /// Для каких платформ скачать Java, можно указать несколько раз
+    #[arg(long = "java-platform", value_enum, value_name = "PLATFORM")]
+    pub java_platforms: Vec<Platform>,
+    /// Откуда качать Java
+    #[arg(long, value_enum, default_value_t = JavaProvider::Mojang)]
+    pub java_provider: JavaProvider,
+    /// JRE или JDK, JDK есть только у adoptium и zulu
+    #[arg(long, value_enum, default_value_t = JavaImage::Jre)]
+    pub java_image: JavaImage,
+    /// Java для процессоров Forge/NeoForge вместо JAVA_HOME и рантайма Mojang
+    #[arg(long, value_name = "PATH")]
+    pub java: Option<PathBuf>,
+    /// Скачать индекс и объекты ассетов в папку сервера
+    #[arg(long)]
+    pub download_assets: bool,
+    /// Сколько файлов качать одновременно
+    #[arg(long, default_value_t = 8)]
+    pub parallel: usize,
+    /// Сколько раз повторять запрос при таймауте, обрыве, 408, 429 или 5xx
+    #[arg(long, default_value_t = 4)]
+    pub retries: u32,
+    /// Таймаут подключения, секунды
+    #[arg(long, default_value_t = 10)]
+    pub connect_timeout: u64,
+    /// Таймаут ожидания данных от сервера, секунды
+    #[arg(long, default_value_t = 30)]
+    pub read_timeout: u64,
+    /// Зеркало `ORIGIN=MIRROR` или `bmclapi`, можно указать несколько раз
+    #[arg(long = "mirror", value_name = "ORIGIN=MIRROR")]
+    pub mirrors: Vec<String>,
+    /// Не использовать общий кеш загрузок
+    #[arg(long, conflicts_with = "offline")]
+    pub no_cache: bool,
+    /// Собрать манифест только из кеша, без сети
+    #[arg(long)]
+    pub offline: bool,
+    /// Собрать строго по neko.lock: ничего, что не закреплено или изменилось с тех пор
+    #[arg(long)]
+    pub locked: bool,
+    /// Как класть файлы из кеша в папку сервера
+    #[arg(long, value_enum, default_value_t = CacheLink::Hardlink)]
+    pub cache_link: CacheLink,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -18,12 +131,18 @@ pub struct Libraries {
     pub name: String,
     pub url: String,
     pub sha1: Option<String>,
+    pub size: Option<u64>,
 }
 
 pub struct MojangResult {
     pub libraries: Vec<Libraries>,
     pub hash_libs: HashSet<LibraryObject>,
     pub asset_index: String,
+    pub main_class: String,
+    pub game: Vec<String>,
+    pub jvm: Vec<String>,
+    pub game_rules: Vec<RuledArgument>,
+    pub jvm_rules: Vec<RuledArgument>,
 }
 
 // Tools types
@@ -38,6 +157,7 @@ pub struct MojangVersionManifest {
 pub struct MojangVersions {
     pub id: String,
     pub url: String,
+    pub sha1: String,
 }
 
 #[derive(Deserialize)]
@@ -50,7 +170,10 @@ pub struct FabricManifest {
 
 #[derive(Deserialize)]
 pub struct FabricArguments {
+    #[serde(default)]
     pub game: Vec<String>,
+    // Quilt не отдаёт `jvm` в профиле
+    #[serde(default)]
     pub jvm: Vec<String>,
 }
 
@@ -59,6 +182,7 @@ pub struct FabricLibraries {
     pub name: String,
     pub url: String,
     pub sha1: Option<String>,
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -67,16 +191,86 @@ pub struct MojangClientManifest {
     pub assetIndex: MojangAssetIndex,
     pub libraries: Vec<MojangLibrary>,
     pub downloads: MojangDownloads,
+    pub mainClass: String,
+    pub arguments: Option<MojangArguments>,
+    // До 1.13 аргументы игры лежат одной строкой
+    pub minecraftArguments: Option<String>,
+    // Нужная версия Java, есть начиная с 1.17
+    pub javaVersion: Option<MojangJavaVersion>,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct MojangJavaVersion {
+    pub component: String,
+    pub majorVersion: u64,
+}
+
+#[derive(Deserialize)]
+pub struct MojangArguments {
+    #[serde(default)]
+    pub game: Vec<MojangArgument>,
+    #[serde(default)]
+    pub jvm: Vec<MojangArgument>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MojangArgument {
+    Plain(String),
+    // Аргументы с правилами (`{ "rules": [...], "value": ... }`)
+    Ruled {
+        rules: Vec<MojangLibraryRule>,
+        value: MojangArgumentValue,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MojangArgumentValue {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+/// Аргумент, который лаунчер добавляет только если выполняются правила (ОС, features)
+#[derive(Serialize)]
+pub struct RuledArgument {
+    pub rules: Vec<MojangLibraryRule>,
+    pub value: Vec<String>,
 }
 
 #[derive(Deserialize)]
 pub struct MojangDownloads {
     pub client: MojangArtifact,
+    // Маппинги для процессоров Forge/NeoForge (DOWNLOAD_MOJMAPS), есть с 1.14.4
+    pub client_mappings: Option<MojangArtifact>,
 }
 
 #[derive(Deserialize)]
+#[allow(non_snake_case)]
 pub struct MojangAssetIndex {
     pub id: String,
+    pub url: String,
+    pub sha1: String,
+    pub size: u64,
+    pub totalSize: u64,
+}
+
+#[derive(Deserialize)]
+pub struct AssetIndex {
+    pub objects: HashMap<String, AssetObject>,
+    // legacy (1.6): лаунчер дополнительно раскладывает файлы в assets/virtual/<id>
+    #[serde(default, rename = "virtual")]
+    pub is_virtual: bool,
+    // pre-1.6: файлы лежат в <папка игры>/resources
+    #[serde(default)]
+    pub map_to_resources: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AssetObject {
+    pub hash: String,
+    pub size: u64,
 }
 
 #[derive(Deserialize)]
@@ -84,27 +278,38 @@ pub struct MojangLibrary {
     pub downloads: MojangLibraryDownloads,
     pub name: String,
     pub rules: Option<Vec<MojangLibraryRule>>,
+    // ОС -> классификатор нативок, например "natives-windows-${arch}"
+    pub natives: Option<HashMap<OsType, String>>,
+    pub extract: Option<LibraryExtract>,
 }
 
 #[derive(Deserialize)]
 pub struct MojangLibraryDownloads {
-    pub artifact: MojangArtifact,
-    // #[serde(rename = "classifiers")]
-    // pub classifiers: HashMap<String, MojangArtifact>,
+    pub artifact: Option<MojangArtifact>,
+    pub classifiers: Option<HashMap<String, MojangArtifact>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct MojangLibraryRule {
     pub action: String,
-    pub os: MojangOs,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<MojangOs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<HashMap<String, bool>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct MojangOs {
-    pub name: OsType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<OsType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    // Регулярное выражение по версии ОС, например "^10\\.5\\.\\d$"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum OsType {
     #[serde(rename = "windows")]
     Windows,
@@ -118,6 +323,7 @@ pub enum OsType {
 pub struct MojangArtifact {
     pub path: Option<String>,
     pub sha1: String,
+    pub size: Option<u64>,
     pub url: String,
 }
 
@@ -129,36 +335,178 @@ pub struct NekoManifest {
     pub libraries: HashSet<LibraryObject>,
     pub jvm: Vec<String>,
     pub game: Vec<String>,
+    /// Аргументы с условиями, лаунчер проверяет правила сам
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jvmRules: Vec<RuledArgument>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gameRules: Vec<RuledArgument>,
     pub verify: Vec<String>,
     pub ignore: Vec<String>,
+    /// Содержимое `verify`: относительный путь -> хеш и размер (без `ignore`)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, FileObject>,
+    /// Рантаймы Java по платформам (`--java-platform`)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub java: BTreeMap<Platform, JavaObject>,
+}
+
+#[derive(Serialize)]
+pub struct JavaObject {
+    /// Папка рантайма относительно папки сервера
+    pub path: String,
+    pub component: String,
+    pub version: String,
+    /// Путь внутри рантайма -> хеш, размер и флаг executable
+    pub files: BTreeMap<String, JavaFileObject>,
+    /// Симлинки: путь -> цель, лаунчер создаёт их сам
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JavaFileObject {
+    pub sha1: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub executable: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileObject {
+    pub sha1: String,
+    pub size: u64,
+}
+
+/// Платформа клиента, названия совпадают с платформами java-runtime у Mojang
+#[derive(
+    Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Platform {
+    WindowsX64,
+    WindowsX86,
+    WindowsArm64,
+    MacOs,
+    MacOsArm64,
+    Linux,
+    LinuxI386,
+    LinuxArm64,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 8] = [
+        Platform::WindowsX64,
+        Platform::WindowsX86,
+        Platform::WindowsArm64,
+        Platform::MacOs,
+        Platform::MacOsArm64,
+        Platform::Linux,
+        Platform::LinuxI386,
+        Platform::LinuxArm64,
+    ];
+
+    /// Имя платформы в all.json и manifest.json
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::WindowsX64 => "windows-x64",
+            Platform::WindowsX86 => "windows-x86",
+            Platform::WindowsArm64 => "windows-arm64",
+            Platform::MacOs => "mac-os",
+            Platform::MacOsArm64 => "mac-os-arm64",
+            Platform::Linux => "linux",
+            Platform::LinuxI386 => "linux-i386",
+            Platform::LinuxArm64 => "linux-arm64",
+        }
+    }
+
+    pub fn os(&self) -> OsType {
+        match self {
+            Platform::WindowsX64 | Platform::WindowsX86 | Platform::WindowsArm64 => OsType::Windows,
+            Platform::MacOs | Platform::MacOsArm64 => OsType::MacOs,
+            Platform::Linux | Platform::LinuxI386 | Platform::LinuxArm64 => OsType::Linux,
+        }
+    }
+
+    /// Архитектура в терминах правил Mojang (`os.arch`)
+    pub fn arch(&self) -> &'static str {
+        match self {
+            Platform::WindowsX64 | Platform::MacOs | Platform::Linux => "x86_64",
+            Platform::WindowsX86 | Platform::LinuxI386 => "x86",
+            Platform::WindowsArm64 | Platform::MacOsArm64 | Platform::LinuxArm64 => "arm64",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct LibraryObject {
     pub path: String,
     pub os: Vec<OsType>,
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Нативки (до 1.19): лаунчер должен распаковать jar в папку natives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<LibraryExtract>,
+}
+
+impl LibraryObject {
+    /// `os` выводится из платформ, чтобы старые лаунчеры продолжали работать
+    pub fn new(path: String, mut platforms: Vec<Platform>) -> Self {
+        platforms.sort();
+        platforms.dedup();
+
+        let mut os = Vec::new();
+        for candidate in [OsType::Windows, OsType::Linux, OsType::MacOs] {
+            if platforms.iter().any(|platform| platform.os() == candidate) {
+                os.push(candidate);
+            }
+        }
+
+        LibraryObject {
+            path,
+            os,
+            platforms,
+            sha1: None,
+            size: None,
+            extract: None,
+        }
+    }
 }
 
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct LibraryExtract {
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// all.json: рантаймы по платформам (`linux`, `windows-x64`, `gamecore`, ...)
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "kebab-case")]
 pub struct JavaRuntime {
-    pub linux: PlatformVersions,
-    pub mac_os: PlatformVersions,
-    pub mac_os_arm64: PlatformVersions,
-    pub windows_x64: PlatformVersions,
-    pub windows_arm64: PlatformVersions,
+    #[serde(flatten)]
+    pub platforms: HashMap<String, PlatformVersions>,
 }
 
+impl JavaRuntime {
+    pub fn platform(&self, platform: Platform) -> Option<&PlatformVersions> {
+        self.platforms.get(platform.name())
+    }
+}
+
+/// Рантаймы платформы по имени компонента: `jre-legacy`, `java-runtime-delta` и любые новые
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "kebab-case")]
 pub struct PlatformVersions {
-    pub java_runtime_alpha: Vec<VersionDetails>,
-    pub java_runtime_beta: Vec<VersionDetails>,
-    pub java_runtime_delta: Vec<VersionDetails>,
-    pub java_runtime_gamma: Vec<VersionDetails>,
-    pub java_runtime_gamma_snapshot: Vec<VersionDetails>,
-    pub jre_legacy: Vec<VersionDetails>,
-    pub minecraft_java_exe: Vec<VersionDetails>,
+    #[serde(flatten)]
+    pub components: HashMap<String, Vec<VersionDetails>>,
+}
+
+impl PlatformVersions {
+    /// Актуальная сборка компонента, `None` если для платформы его нет
+    pub fn component(&self, name: &str) -> Option<&VersionDetails> {
+        self.components.get(name)?.first()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -187,6 +535,46 @@ pub struct Version {
     pub released: String,
 }
 
+/// Сборка из `api.adoptium.net/v3/assets/latest`
+#[derive(Deserialize)]
+pub struct AdoptiumRelease {
+    pub binary: AdoptiumBinary,
+    pub version: AdoptiumVersion,
+}
+
+#[derive(Deserialize)]
+pub struct AdoptiumBinary {
+    pub package: AdoptiumPackage,
+}
+
+#[derive(Deserialize)]
+pub struct AdoptiumPackage {
+    /// SHA-256
+    pub checksum: String,
+    pub link: String,
+    pub size: u64,
+}
+
+#[derive(Deserialize)]
+pub struct AdoptiumVersion {
+    pub openjdk_version: String,
+}
+
+/// Пакет из `api.azul.com/metadata/v1/zulu/packages`
+#[derive(Deserialize)]
+pub struct ZuluPackage {
+    pub package_uuid: String,
+    pub download_url: String,
+    pub java_version: Vec<u32>,
+}
+
+/// `packages/<uuid>`: хеш и размер есть только здесь
+#[derive(Deserialize)]
+pub struct ZuluPackageDetails {
+    pub sha256_hash: String,
+    pub size: u64,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SelectedJavaManifest {
     pub files: HashMap<String, FileEntry>,
@@ -205,6 +593,10 @@ pub enum FileEntry {
         executable: bool,
     },
 
+    /// Симлинк внутри рантайма (`legal/`, `lib` на macOS)
+    #[serde(rename = "link")]
+    Link { target: String },
+
     #[serde(other)]
     Ignored, // Игнорируем все неизвестные значения
 }
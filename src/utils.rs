@@ -1,20 +1,30 @@
 use std::{
-    fs::{self, create_dir_all, File},
-    io::{Cursor, Write},
-    path::Path,
-    process::Command,
+    fs::{create_dir_all, File},
+    io::Read,
+    path::{Path, PathBuf},
 };
 
-use reqwest::Client;
+use reqwest::StatusCode;
+use sha1::{Digest, Sha1};
 use zip::ZipArchive;
 
-use crate::forgeinstaller::ForgeClientManifest;
+use crate::download::{
+    download_file, download_libraries, fetch_maven_sha1, DownloadError, DownloadTask,
+};
+use crate::forgeinstaller::{
+    ForgeArguments, ForgeArtifact, ForgeClientManifest, ForgeLibrary, ForgeLibraryDownloads,
+    LegacyInstallProfile,
+};
+use crate::http::FetchError;
+use crate::types::Libraries;
 
+/// `group:artifact:version[:classifier][@ext]` -> путь в Maven-репозитории
 pub fn resolve_maven(maven: &str) -> String {
-    let parts: Vec<&str> = maven.split(':').collect();
+    let (coords, extension) = maven.split_once('@').unwrap_or((maven, "jar"));
+    let parts: Vec<&str> = coords.split(':').collect();
 
     if parts.len() < 3 {
-        return format!("{}", maven);
+        return maven.to_string();
     }
 
     let group = parts[0].replace('.', "/");
@@ -28,11 +38,28 @@ pub fn resolve_maven(maven: &str) -> String {
     };
 
     format!(
-        "{}/{}/{}/{}-{}{}.jar",
-        group, artifact, version, artifact, version, classifier
+        "{}/{}/{}/{}-{}{}.{}",
+        group, artifact, version, artifact, version, classifier, extension
     )
 }
 
+/// Путь библиотеки относительно папки сервера, клиент лежит в корне как `minecraft.jar`
+pub fn library_path(name: &str) -> String {
+    if name == "minecraft.jar" {
+        return name.to_owned();
+    }
+
+    format!("libraries/{}", resolve_maven(name))
+}
+
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    hex(&Sha1::digest(bytes))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 pub fn default_jvm_args() -> Vec<String> {
     vec![
         "-XX:+IgnoreUnrecognizedVMOptions".to_string(),
@@ -49,136 +76,284 @@ pub fn default_jvm_args() -> Vec<String> {
     ]
 }
 
-pub async fn get_loader_install_profile(
+fn loader_installer_url(
     loader_type: &str,
     mc_version: &str,
     loader_version: &str,
-) -> Result<ForgeClientManifest, Box<dyn std::error::Error>> {
-    let url = match loader_type {
-        "forge" => format!(
+) -> Option<String> {
+    match loader_type {
+        "forge" => Some(format!(
             "https://maven.minecraftforge.net/net/minecraftforge/forge/{mc}-{loader}/forge-{mc}-{loader}-installer.jar",
             mc = mc_version,
             loader = loader_version
-        ),
-        "neoforge" => format!(
+        )),
+        "neoforge" => Some(format!(
             "https://maven.neoforged.net/releases/net/neoforged/neoforge/{loader}/neoforge-{loader}-installer.jar",
             loader = loader_version
-        ),
-        _ => return Err("Unknown loader type".into()),
+        )),
+        _ => None,
+    }
+}
+
+/// Установщик качается во временную папку и не перекачивается, пока совпадает с `.sha1` из Maven
+pub async fn download_loader_installer(
+    loader_type: &str,
+    mc_version: &str,
+    loader_version: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let url = loader_installer_url(loader_type, mc_version, loader_version)
+        .ok_or("Unknown loader type")?;
+
+    let path = std::env::temp_dir().join(format!(
+        "neko-{}-{}-{}-installer.jar",
+        loader_type, mc_version, loader_version
+    ));
+
+    let result = match download_installer(&url, &path).await {
+        // Forge для 1.7.10 и старше публиковался с суффиксом версии игры: 10.13.4.1614-1.7.10.
+        // В `--offline` и `--locked` 404 не узнать, поэтому суффикс пробуем и при промахе
+        Err(e) if is_not_found(&e) && loader_type == "forge" => {
+            let suffixed = format!("{}-{}", loader_version, mc_version);
+            match loader_installer_url(loader_type, mc_version, &suffixed) {
+                Some(url) => download_installer(&url, &path).await,
+                None => Err(e),
+            }
+        }
+        resp => resp,
     };
 
-    let client = Client::new();
-    let resp = client.get(&url).send().await?.bytes().await?;
-    let reader = Cursor::new(resp);
-    let mut archive = ZipArchive::new(reader)?;
+    result.map_err(|e| e as Box<dyn std::error::Error>)?;
+
+    Ok(path)
+}
+
+async fn download_installer(url: &str, path: &Path) -> Result<(), DownloadError> {
+    download_file(&DownloadTask {
+        name: url.rsplit('/').next().unwrap_or(url).to_owned(),
+        url: url.to_owned(),
+        path: path.to_path_buf(),
+        sha1: fetch_maven_sha1(url).await,
+        size: None,
+    })
+    .await
+}
+
+fn is_not_found(e: &DownloadError) -> bool {
+    e.downcast_ref::<FetchError>()
+        .is_some_and(|e| e.status() == Some(StatusCode::NOT_FOUND) || e.is_miss())
+}
+
+pub async fn get_loader_install_profile(
+    loader_type: &str,
+    mc_version: &str,
+    loader_version: &str,
+) -> Result<ForgeClientManifest, Box<dyn std::error::Error>> {
+    let installer = download_loader_installer(loader_type, mc_version, loader_version).await?;
+    let mut archive = ZipArchive::new(File::open(installer)?)?;
+
+    if archive.index_for_name("version.json").is_none() {
+        let mut profile = String::new();
+        archive
+            .by_name("install_profile.json")?
+            .read_to_string(&mut profile)?;
+
+        let legacy_profile: LegacyInstallProfile = serde_json::from_str(&profile)?;
+
+        return Ok(legacy_client_manifest(legacy_profile));
+    }
 
     let mut version = String::new();
     {
         let mut version_file = archive.by_name("version.json")?;
-        use std::io::Read;
         version_file.read_to_string(&mut version)?;
     }
 
-    let version_json: ForgeClientManifest = serde_json::from_str(&version)?;
+    let mut version_json: ForgeClientManifest = serde_json::from_str(&version)?;
+
+    if let Some(minecraft_arguments) = &version_json.minecraftArguments {
+        version_json.arguments.game = split_arguments(minecraft_arguments);
+    }
 
     Ok(version_json)
 }
 
-pub async fn run_loader_installer(
-    loader_type: &str,
-    mc_version: String,
-    loader_version: String,
-    server_name: String,
-) {
-    let url = match loader_type {
-        "forge" => format!(
-            "https://maven.minecraftforge.net/net/minecraftforge/forge/{mc}-{loader}/forge-{mc}-{loader}-installer.jar",
-            mc = mc_version,
-            loader = loader_version
-        ),
-        "neoforge" => format!(
-            "https://maven.neoforged.net/releases/net/neoforged/neoforge/{loader}/neoforge-{loader}-installer.jar",
-            loader = loader_version
-        ),
-        _ => panic!("Unknown loader type"),
-    };
+fn split_arguments(arguments: &str) -> Vec<String> {
+    arguments
+        .split_whitespace()
+        .map(|arg| arg.to_owned())
+        .collect()
+}
 
-    let client = Client::new();
-    let resp = client
-        .get(&url)
-        .send()
-        .await
-        .expect("Failed to download installer")
-        .bytes()
-        .await
-        .expect("Failed to read installer bytes");
-
-    let installer_name = match loader_type {
-        "forge" => "forge-installer.jar",
-        "neoforge" => "neoforge-installer.jar",
-        _ => "installer.jar",
-    };
+/// Приводит install_profile.json старого установщика к формату version.json
+fn legacy_client_manifest(profile: LegacyInstallProfile) -> ForgeClientManifest {
+    let version_info = profile.versionInfo;
 
-    let installer_path = format!(
-        "{server}/{installer}",
-        server = server_name,
-        installer = installer_name
-    );
-    let installer_path_ref = Path::new(&installer_path);
-
-    create_dir_all(&server_name).expect("Failed to create target directory");
-
-    let profiles_path = Path::new(&server_name).join("launcher_profiles.json");
-
-    let json = r#"{
-      "profiles": {},
-      "settings": {
-        "crashAssistance": false,
-        "enableAdvanced": false,
-        "enableAnalytics": true,
-        "enableHistorical": false,
-        "enableReleases": true,
-        "enableSnapshots": false,
-        "keepLauncherOpen": false,
-        "profileSorting": "ByLastPlayed",
-        "showGameLog": true,
-        "showMenu": false,
-        "soundOn": false
-      },
-      "version": 4
-    }"#;
-
-    let mut profiles_file = File::create(profiles_path.clone()).unwrap();
-    profiles_file
-        .write_all(json.as_bytes())
-        .expect("Failed to write profiles json");
-
-    let mut file = File::create(installer_path_ref).expect("Failed to create installer file");
-    std::io::copy(&mut Cursor::new(resp), &mut file).expect("Failed to write installer file");
-
-    let args = [
-        "-jar",
-        &format!("{}/{}", &server_name.clone(), installer_name),
-        "--installClient",
-        &server_name.clone(),
-    ];
-
-    let status = Command::new("java")
-        .args(args)
-        .current_dir(".")
-        .status()
-        .expect("Failed to run installer");
-
-    if !status.success() {
-        panic!("Installer failed with status: {}", status);
+    let libraries = version_info
+        .libraries
+        .into_iter()
+        // Нативки lwjgl 2 приходят из манифеста Mojang
+        .filter(|lib| lib.natives.is_none())
+        .map(|lib| {
+            let path = resolve_maven(&lib.name);
+            let url = if lib.name == profile.install.path {
+                // universal jar лежит внутри установщика
+                String::new()
+            } else {
+                let repository = lib
+                    .url
+                    .unwrap_or_else(|| "https://libraries.minecraft.net/".to_owned())
+                    .replace(
+                        "http://files.minecraftforge.net/maven/",
+                        "https://maven.minecraftforge.net/",
+                    );
+                format!("{}{}", repository, path)
+            };
+
+            ForgeLibrary {
+                name: lib.name,
+                downloads: Some(ForgeLibraryDownloads {
+                    artifact: ForgeArtifact {
+                        path,
+                        url,
+                        // Несколько сумм бывает у библиотек с .pack.xz, тогда не угадываем
+                        sha1: lib
+                            .checksums
+                            .filter(|checksums| checksums.len() == 1)
+                            .and_then(|checksums| checksums.into_iter().next())
+                            .unwrap_or_default(),
+                        size: None,
+                    },
+                }),
+            }
+        })
+        .collect();
+
+    ForgeClientManifest {
+        id: version_info.id,
+        inheritsFrom: version_info.inheritsFrom,
+        mainClass: version_info.mainClass,
+        libraries,
+        arguments: ForgeArguments {
+            game: split_arguments(&version_info.minecraftArguments),
+            jvm: Vec::new(),
+        },
+        minecraftArguments: Some(version_info.minecraftArguments),
+        legacy: Some(profile.install),
+    }
+}
+
+/// Forge до 1.13 заменяет ванильную строку minecraftArguments целиком, а не дополняет её:
+/// ключи остаются в ванильном порядке, значение берётся у лоадера (`--versionType Forge`),
+/// а ключи только от лоадера (`--tweakClass`) идут в конце
+pub fn merge_legacy_arguments(vanilla: Vec<String>, loader: Vec<String>) -> Vec<String> {
+    let mut merged = argument_pairs(vanilla);
+
+    for (key, value) in argument_pairs(loader) {
+        match merged.iter_mut().find(|(existing, _)| *existing == key) {
+            Some(existing) => existing.1 = value,
+            None => merged.push((key, value)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .flat_map(|(key, value)| std::iter::once(key).chain(value))
+        .collect()
+}
+
+/// `--key value` и одиночные `--flag`
+fn argument_pairs(arguments: Vec<String>) -> Vec<(String, Option<String>)> {
+    let mut pairs = Vec::new();
+    let mut iter = arguments.into_iter().peekable();
+
+    while let Some(key) = iter.next() {
+        let value = iter.next_if(|v| !v.starts_with("--"));
+        pairs.push((key, value));
     }
 
-    println!("Cleanup");
+    pairs
+}
+
+/// Forge до 1.13: установщик не умеет --installClient, поэтому достаём universal jar
+/// и качаем библиотеки сами
+pub async fn install_legacy_loader(
+    mc_version: &str,
+    loader_version: &str,
+    server_name: &str,
+    manifest: &ForgeClientManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let install = manifest.legacy.as_ref().ok_or("Not a legacy installer")?;
+
+    let installer = download_loader_installer("forge", mc_version, loader_version).await?;
+    let mut archive = ZipArchive::new(File::open(installer)?)?;
+
+    let universal_path = Path::new(server_name)
+        .join("libraries")
+        .join(resolve_maven(&install.path));
+    create_dir_all(universal_path.parent().unwrap())?;
+
+    let mut universal = File::create(&universal_path)?;
+    std::io::copy(&mut archive.by_name(&install.filePath)?, &mut universal)?;
+
+    println!(
+        "{}: {} -> {:?}",
+        install.path, install.filePath, universal_path
+    );
+
+    let libs: Vec<Libraries> = manifest
+        .libraries
+        .iter()
+        .filter_map(|lib| {
+            let artifact = &lib.downloads.as_ref()?.artifact;
+
+            // Без url только сам Forge (universal jar) - он уже извлечён из установщика выше
+            if artifact.url.is_empty() {
+                return None;
+            }
+
+            Some(Libraries {
+                name: lib.name.clone(),
+                url: artifact.url.clone(),
+                sha1: Some(artifact.sha1.clone()),
+                size: artifact.size,
+            })
+        })
+        .collect();
+
+    download_libraries(server_name, &libs).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_forge_arguments_replace_vanilla_values() {
+        // minecraftArguments из 1.12.2.json и Forge 1.12.2-14.23.5.2859
+        let vanilla = split_arguments(
+            "--username ${auth_player_name} --version ${version_name} --gameDir ${game_directory} --assetsDir ${assets_root} --assetIndex ${assets_index_name} --uuid ${auth_uuid} --accessToken ${auth_access_token} --userType ${user_type} --versionType ${version_type}",
+        );
+        let forge = split_arguments(
+            "--username ${auth_player_name} --version ${version_name} --gameDir ${game_directory} --assetsDir ${assets_root} --assetIndex ${assets_index_name} --uuid ${auth_uuid} --accessToken ${auth_access_token} --userType ${user_type} --tweakClass net.minecraftforge.fml.common.launcher.FMLTweaker --versionType Forge",
+        );
+
+        let merged = merge_legacy_arguments(vanilla, forge);
+
+        assert_eq!(
+            merged,
+            split_arguments(
+                "--username ${auth_player_name} --version ${version_name} --gameDir ${game_directory} --assetsDir ${assets_root} --assetIndex ${assets_index_name} --uuid ${auth_uuid} --accessToken ${auth_access_token} --userType ${user_type} --versionType Forge --tweakClass net.minecraftforge.fml.common.launcher.FMLTweaker",
+            )
+        );
+        assert_eq!(
+            merged.iter().filter(|arg| *arg == "--versionType").count(),
+            1
+        );
+    }
 
-    fs::remove_file(profiles_path).unwrap();
-    fs::remove_file(installer_path).unwrap();
-    if loader_type == "forge" {
-        fs::remove_file(Path::new(&server_name).join("installer.log")).unwrap();
+    #[test]
+    fn unknown_loader_has_no_installer() {
+        assert!(loader_installer_url("fabric", "1.20.1", "0.15.7").is_none());
+        assert!(loader_installer_url("forge", "1.12.2", "14.23.5.2859").is_some());
     }
-    fs::remove_dir_all(Path::new(&server_name).join("versions")).unwrap();
 }
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    download::download_libraries, manifest::write_manifest, mojang::parse_mojang,
+    types::NekoManifest, utils::default_jvm_args,
+};
+
+pub async fn create_vanilla_manifest(
+    server_name: String,
+    mc_version: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mojang_parsed = parse_mojang(mc_version.clone()).await?;
+
+    download_libraries(&server_name, &mojang_parsed.libraries).await?;
+
+    let mut jvm = default_jvm_args();
+
+    jvm.extend(mojang_parsed.jvm);
+
+    let neko_manifest = NekoManifest {
+        mainclass: mojang_parsed.main_class,
+        assetIndex: mojang_parsed.asset_index,
+        libraries: mojang_parsed.hash_libs,
+        jvm,
+        game: mojang_parsed.game,
+        jvmRules: mojang_parsed.jvm_rules,
+        gameRules: mojang_parsed.game_rules,
+        verify: vec!["libraries".to_string(), "minecraft.jar".to_string()],
+        ignore: vec!["options.txt".to_string()],
+        files: BTreeMap::new(),
+        java: BTreeMap::new(),
+    };
+
+    write_manifest(&server_name, neko_manifest).await
+}